@@ -0,0 +1,310 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::confirm::{self, Acknowledgement, ConfirmationState, DEFAULT_WAIT_TIMEOUT_MS, MAX_WAIT_TIMEOUT_MS};
+use crate::desired::{self, DesiredConfig, InstanceState};
+use crate::error::ApiError;
+use crate::extract::{Json, Query};
+use crate::validation::{self, FieldError};
+use crate::writer::{WriteFailure, WriteOptions};
+use crate::{AppState, SensorConfig, SensorStatus};
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub(crate) struct BatchWriteQuery {
+    /// Wait until every sensor reports a SensorStatus matching its written config
+    pub(crate) wait: Option<bool>,
+    /// Wait until every matched reader acknowledged the whole batch, needs a reliable QoS
+    pub(crate) ack: Option<bool>,
+    /// How long to wait for each of `ack` and `wait`, 5000 by default and at most 60000
+    pub(crate) timeout_ms: Option<u64>,
+    /// Re-publish the previous config of the written sensors when a write or confirmation fails
+    pub(crate) rollback: Option<bool>,
+}
+
+impl BatchWriteQuery {
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.timeout_ms
+                .unwrap_or(DEFAULT_WAIT_TIMEOUT_MS)
+                .min(MAX_WAIT_TIMEOUT_MS),
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BatchItemState {
+    /// Written, and acknowledged with `ack=true`
+    Written,
+    /// Written and reported by the sensor, with `wait=true`
+    Applied,
+    /// Written, but not acknowledged or not reported by the sensor in time
+    Unconfirmed,
+    /// The write failed
+    Failed,
+    /// Not written because an earlier item failed
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RollbackState {
+    /// The previous config was written again
+    RolledBack,
+    /// The sensor had no config before, or its instance was removed. Only the desired config
+    /// is restored
+    NoPreviousConfig,
+    /// Writing the previous config failed
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct BatchItemResult {
+    pub(crate) config: SensorConfig,
+    pub(crate) state: BatchItemState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    /// Last status reported during the wait, with `wait=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) status: Option<SensorStatus>,
+    /// Present when the batch was rolled back and this item may have been written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) rollback: Option<RollbackState>,
+}
+
+impl BatchItemResult {
+    fn new(config: SensorConfig, state: BatchItemState) -> Self {
+        BatchItemResult {
+            config,
+            state,
+            error: None,
+            status: None,
+            rollback: None,
+        }
+    }
+
+    fn written(&self) -> bool {
+        matches!(
+            self.state,
+            BatchItemState::Written | BatchItemState::Applied | BatchItemState::Unconfirmed
+        )
+    }
+}
+
+/// Result of `PUT /sensor/config/batch`, one item per config in request order.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct BatchReport {
+    /// Every config was written and, when requested, confirmed
+    pub(crate) committed: bool,
+    pub(crate) rolled_back: bool,
+    /// Acknowledgement of the whole batch, with `ack=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) acknowledgement: Option<Acknowledgement>,
+    pub(crate) items: Vec<BatchItemResult>,
+}
+
+/// Checks every config before anything is written.
+fn validate_batch(state: &AppState, configs: &[SensorConfig]) -> Result<(), ApiError> {
+    if configs.is_empty() {
+        return Err(ApiError::bad_request("the batch holds no config"));
+    }
+    let mut sensor_types = HashSet::new();
+    let mut errors = vec![];
+    for (i, config) in configs.iter().enumerate() {
+        if !sensor_types.insert(config.sensor_type.as_str()) {
+            return Err(ApiError::bad_request(format!(
+                "{} appears more than once in the batch",
+                config.sensor_type
+            )));
+        }
+        let report = validation::validate_config(&state.validation, config);
+        errors.extend(report.errors.into_iter().map(|error| FieldError {
+            field: format!("[{i}].{}", error.field),
+            message: error.message,
+        }));
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let detail = errors
+        .iter()
+        .map(|error| format!("{}: {}", error.field, error.message))
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(ApiError::validation(detail, errors))
+}
+
+/// Writes the previous config of every item that may have been written and restores the
+/// desired configs loaded before the batch.
+async fn rollback(state: &AppState, items: &mut [BatchItemResult], previous: &[Option<DesiredConfig>]) {
+    let Some(writer) = state.writer.get() else {
+        return;
+    };
+    let mut republish = vec![];
+    for (i, item) in items.iter_mut().enumerate().filter(|(_, item)| item.written()) {
+        match &previous[i] {
+            Some(DesiredConfig {
+                config: Some(config),
+                instance_state: InstanceState::Alive,
+                ..
+            }) => republish.push((i, config.clone())),
+            _ => item.rollback = Some(RollbackState::NoPreviousConfig),
+        }
+    }
+    if republish.is_empty() {
+        return restore_desired(state, items, previous);
+    }
+    let configs = republish.iter().map(|(_, config)| config.clone()).collect();
+    let written = match writer.write_set(configs, WriteOptions::default()).await {
+        Ok(outcome) => outcome.written,
+        Err(e) => vec![Err(e)],
+    };
+    for (n, (i, _)) in republish.iter().enumerate() {
+        let item = &mut items[*i];
+        item.rollback = Some(match written.get(n) {
+            Some(Ok(_)) => RollbackState::RolledBack,
+            Some(Err(e)) => {
+                println!("batch: cannot roll back {}: {e}", item.config.sensor_type);
+                RollbackState::Failed
+            }
+            None => RollbackState::Failed,
+        });
+    }
+    restore_desired(state, items, previous);
+}
+
+/// The rolled back sensors get the desired config they had before the batch.
+fn restore_desired(state: &AppState, items: &[BatchItemResult], previous: &[Option<DesiredConfig>]) {
+    for (i, item) in items.iter().enumerate() {
+        if item.rollback.is_some_and(|rollback| rollback != RollbackState::Failed) {
+            if let Err(e) = desired::restore(&state.desired, &item.config.sensor_type, previous[i].as_ref()) {
+                println!("batch: cannot restore desired config of {}: {e}", item.config.sensor_type);
+            }
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/sensor/config/batch",
+    params(BatchWriteQuery),
+    request_body = [SensorConfig],
+    responses(
+        (status = 200, body = BatchReport, description = "Every config was written back to back, without other SensorConfig writes in between, \
+            and confirmed as requested with `ack` and `wait`"),
+        (status = 207, body = BatchReport, description = "A write or confirmation failed. Later items are skipped, \
+            with `rollback=true` the written sensors get their previous config again"),
+        (status = 400, body = Problem, content_type = "application/problem+json", description = "Empty batch, a sensor appears twice, \
+            or `ack=true` on a SensorConfig topic without reliable QoS"),
+        (status = 422, body = Problem, content_type = "application/problem+json", description = "A config violates the capability profile of its sensor type, nothing is published"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed"),
+        (status = 503, body = Problem, content_type = "application/problem+json", description = "DDS is not connected yet")
+    ),
+    tag = "put_handler_sensor_config_batch"
+)]
+pub(crate) async fn put_handler_sensor_config_batch(
+    State(state): State<AppState>,
+    Query(query): Query<BatchWriteQuery>,
+    Json(payload): Json<Vec<SensorConfig>>,
+) -> Result<(StatusCode, Json<BatchReport>), ApiError> {
+    validate_batch(&state, &payload)?;
+    let writer = state.writer.get().ok_or_else(ApiError::dds_unavailable)?;
+    let timeout = query.timeout();
+    let previous = payload
+        .iter()
+        .map(|config| desired::load_desired(&state.desired, &config.sensor_type))
+        .collect::<sled::Result<Vec<_>>>()?;
+    // subscribe before writing so that a fast reply is not missed
+    let status_events = query
+        .wait
+        .unwrap_or(false)
+        .then(|| payload.iter().map(|_| state.status_events.subscribe()).collect::<Vec<_>>());
+    let options = WriteOptions {
+        require_matched: false,
+        ack: query.ack.unwrap_or(false).then_some(timeout),
+        record_desired: true,
+        coalesce: false,
+    };
+
+    let mut items = payload
+        .iter()
+        .map(|config| BatchItemResult::new(config.clone(), BatchItemState::Skipped))
+        .collect::<Vec<_>>();
+    let mut acknowledgement = None;
+    match writer.write_set(payload.clone(), options).await {
+        Ok(outcome) => {
+            for (item, written) in items.iter_mut().zip(outcome.written) {
+                match written {
+                    Ok(_) => item.state = BatchItemState::Written,
+                    Err(e) => {
+                        item.state = BatchItemState::Failed;
+                        item.error = Some(e.to_string());
+                    }
+                }
+            }
+            acknowledgement = outcome.acknowledgement;
+        }
+        Err(WriteFailure::NotReliable) => return Err(WriteFailure::NotReliable.into()),
+        // the batch may still be written later, so every item counts as possibly written
+        Err(e) => {
+            for item in &mut items {
+                item.state = BatchItemState::Unconfirmed;
+                item.error = Some(e.to_string());
+            }
+        }
+    }
+
+    if acknowledgement.as_ref().is_some_and(|acknowledgement| !acknowledgement.complete()) {
+        for item in items.iter_mut().filter(|item| item.written()) {
+            item.state = BatchItemState::Unconfirmed;
+            item.error = Some("not acknowledged by every matched reader in time".to_string());
+        }
+    }
+    let all_written = items.iter().all(|item| item.state == BatchItemState::Written);
+    if let (Some(status_events), true) = (status_events, all_written) {
+        let waits = status_events
+            .into_iter()
+            .zip(&payload)
+            .map(|(events, config)| confirm::wait_for_status(events, config.clone(), timeout));
+        let confirmations = futures::future::join_all(waits).await;
+        for (item, confirmation) in items.iter_mut().zip(confirmations) {
+            item.status = confirmation.status;
+            match confirmation.state {
+                ConfirmationState::Applied => item.state = BatchItemState::Applied,
+                ConfirmationState::Pending => {
+                    item.state = BatchItemState::Unconfirmed;
+                    item.error = Some("the sensor reported other values".to_string());
+                }
+                ConfirmationState::TimedOut => {
+                    item.state = BatchItemState::Unconfirmed;
+                    item.error = Some(format!("no status within {} ms", confirmation.waited_ms));
+                }
+            }
+        }
+    }
+
+    let committed = items
+        .iter()
+        .all(|item| matches!(item.state, BatchItemState::Written | BatchItemState::Applied));
+    let rolled_back = !committed && query.rollback.unwrap_or(false);
+    if rolled_back {
+        rollback(&state, &mut items, &previous).await;
+    }
+    let status_code = if committed {
+        StatusCode::OK
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    Ok((
+        status_code,
+        Json(BatchReport {
+            committed,
+            rolled_back,
+            acknowledgement,
+            items,
+        }),
+    ))
+}
@@ -0,0 +1,951 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use rustdds::policy::{Deadline, Durability, History, Lifespan, Liveliness, Ownership, Reliability};
+use rustdds::{QosPolicies, QosPolicyBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use utoipa::ToSchema;
+
+use crate::dynamic::MAX_DYNAMIC_TOPICS;
+use crate::idl;
+use crate::layout::{FieldLayout, TypeDefs, TypeLayout};
+
+/// Name of the QoS profile used when a topic does not select one.
+pub(crate) const DEFAULT_QOS_PROFILE: &str = "default";
+
+/// Highest domain id that still maps to valid RTPS ports with the default port parameters.
+const MAX_DOMAIN_ID: u16 = 232;
+
+/// Command line arguments. Every option can also be set through its environment variable,
+/// and both take precedence over the configuration file.
+#[derive(Parser, Debug)]
+#[command(version, about = "REST gateway between HTTP clients and DDS sensors")]
+pub(crate) struct Args {
+    /// Path to the TOML configuration file
+    #[arg(short, long, env = "WEBDDS_CONFIG")]
+    pub(crate) config: Option<PathBuf>,
+    /// DDS domain id
+    #[arg(long, env = "WEBDDS_DOMAIN_ID")]
+    pub(crate) domain_id: Option<u16>,
+    /// Address the HTTP server listens on, e.g. localhost:3000
+    #[arg(long, env = "WEBDDS_BIND_ADDRESS")]
+    pub(crate) bind_address: Option<String>,
+    /// Path of the sled database
+    #[arg(long, env = "WEBDDS_DATABASE_PATH")]
+    pub(crate) database_path: Option<PathBuf>,
+    /// Name of the SensorConfig topic
+    #[arg(long, env = "WEBDDS_SENSOR_CONFIG_TOPIC")]
+    pub(crate) sensor_config_topic: Option<String>,
+    /// Name of the SensorStatus topic
+    #[arg(long, env = "WEBDDS_SENSOR_STATUS_TOPIC")]
+    pub(crate) sensor_status_topic: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GatewayConfig {
+    pub(crate) domain_id: u16,
+    pub(crate) bind_address: String,
+    /// Defaults to the name of the SensorStatus topic.
+    pub(crate) database_path: Option<PathBuf>,
+    pub(crate) topics: TopicsConfig,
+    pub(crate) qos: HashMap<String, QosProfile>,
+    pub(crate) retention: RetentionConfig,
+    pub(crate) reconcile: ReconcileConfig,
+    pub(crate) validation: ValidationConfig,
+    pub(crate) health: HealthConfig,
+    pub(crate) writer: WriterConfig,
+    /// IDL files declaring the types of the topics, relative to the configuration file
+    pub(crate) idl_files: Vec<PathBuf>,
+    /// Additional topics bridged without compiled-in Rust types
+    pub(crate) dynamic_topics: Vec<DynamicTopicConfig>,
+    /// Types parsed from `idl_files`
+    #[serde(skip)]
+    pub(crate) idl: TypeDefs,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        GatewayConfig {
+            domain_id: 0,
+            bind_address: String::from("localhost:3000"),
+            database_path: None,
+            topics: TopicsConfig::default(),
+            qos: HashMap::from([(DEFAULT_QOS_PROFILE.to_string(), QosProfile::default())]),
+            retention: RetentionConfig::default(),
+            reconcile: ReconcileConfig::default(),
+            validation: ValidationConfig::default(),
+            health: HealthConfig::default(),
+            writer: WriterConfig::default(),
+            idl_files: vec![],
+            dynamic_topics: vec![],
+            idl: TypeDefs::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TopicsConfig {
+    pub(crate) sensor_config: TopicConfig,
+    pub(crate) sensor_status: TopicConfig,
+}
+
+impl Default for TopicsConfig {
+    fn default() -> Self {
+        TopicsConfig {
+            sensor_config: TopicConfig::named("SensorConfig"),
+            sensor_status: TopicConfig::named("SensorStatus"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TopicConfig {
+    pub(crate) name: String,
+    #[serde(default = "default_qos_profile")]
+    pub(crate) qos_profile: String,
+    /// IDL struct the compiled type is checked against at startup
+    pub(crate) idl_type: Option<String>,
+}
+
+impl TopicConfig {
+    fn named(name: &str) -> Self {
+        TopicConfig {
+            name: name.to_string(),
+            qos_profile: default_qos_profile(),
+            idl_type: None,
+        }
+    }
+}
+
+fn default_qos_profile() -> String {
+    DEFAULT_QOS_PROFILE.to_string()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TopicDirection {
+    /// Expose a REST endpoint writing samples to DDS
+    Publish,
+    /// Store received samples and expose them over REST
+    Subscribe,
+    #[default]
+    Both,
+}
+
+/// Topic whose type is described by its field layout or an IDL struct instead of a Rust struct.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DynamicTopicConfig {
+    pub(crate) name: String,
+    /// DDS type name, defaults to the IDL struct or the topic name
+    pub(crate) type_name: Option<String>,
+    #[serde(default = "default_qos_profile")]
+    pub(crate) qos_profile: String,
+    #[serde(default)]
+    pub(crate) direction: TopicDirection,
+    /// Fields in CDR order
+    #[serde(default)]
+    pub(crate) fields: Vec<FieldLayout>,
+    /// IDL struct used instead of `fields`
+    pub(crate) idl_type: Option<String>,
+}
+
+impl DynamicTopicConfig {
+    pub(crate) fn layout(&self, idl: &TypeDefs) -> TypeLayout {
+        let (type_name, fields) = match &self.idl_type {
+            // existence of the struct is checked in validate()
+            Some(idl_type) => {
+                let idl_type = idl_type.trim_start_matches("::");
+                (idl_type.to_string(), idl.structs[idl_type].clone())
+            }
+            None => (self.name.clone(), self.fields.clone()),
+        };
+        TypeLayout {
+            type_name: self.type_name.clone().unwrap_or(type_name),
+            types: idl.referenced_by(&fields),
+            fields,
+        }
+    }
+
+    fn validate(&self, idl: &TypeDefs) -> Result<()> {
+        let name = &self.name;
+        if name.trim().is_empty() {
+            bail!("dynamic_topics: name must not be empty");
+        }
+        match &self.idl_type {
+            Some(_) if !self.fields.is_empty() => {
+                bail!("dynamic topic \"{name}\": set either fields or idl_type, not both")
+            }
+            Some(idl_type) if !idl.structs.contains_key(idl_type.trim_start_matches("::")) => {
+                bail!("dynamic topic \"{name}\": idl_type {idl_type} is not a struct of idl_files")
+            }
+            Some(_) => {}
+            None if self.fields.is_empty() => {
+                bail!("dynamic topic \"{name}\": fields or idl_type is required")
+            }
+            None => {}
+        }
+        let layout = self.layout(idl);
+        if let Some(unknown) = idl.unknown_type(&layout.fields) {
+            bail!("dynamic topic \"{name}\": type {unknown} is not declared in idl_files");
+        }
+        if !layout.fields.iter().any(|field| field.key) {
+            bail!("dynamic topic \"{name}\": at least one field must have key = true");
+        }
+        for (index, field) in self.fields.iter().enumerate() {
+            if field.name.trim().is_empty() {
+                bail!("dynamic topic \"{name}\": field {index} has an empty name");
+            }
+            if self.fields[..index].iter().any(|other| other.name == field.name) {
+                bail!("dynamic topic \"{name}\": field \"{}\" is declared twice", field.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Limits for the status history. Raw samples removed by a limit are folded into
+/// per-minute and per-hour aggregates first, unless `downsample` is off.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RetentionConfig {
+    /// Seconds between two enforcement passes
+    pub(crate) interval_secs: u64,
+    /// Raw samples older than this are removed
+    pub(crate) max_age_secs: Option<u64>,
+    /// Only the newest samples of each sensor are kept
+    pub(crate) max_samples_per_sensor: Option<usize>,
+    /// Oldest samples are removed until the raw history is below this size
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) downsample: bool,
+    /// Per-minute aggregates older than this are removed
+    pub(crate) minute_aggregate_max_age_secs: Option<u64>,
+    /// Per-hour aggregates older than this are removed
+    pub(crate) hour_aggregate_max_age_secs: Option<u64>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            interval_secs: 60,
+            max_age_secs: None,
+            max_samples_per_sensor: None,
+            max_bytes: None,
+            downsample: true,
+            minute_aggregate_max_age_secs: Some(7 * 24 * 60 * 60),
+            hour_aggregate_max_age_secs: None,
+        }
+    }
+}
+
+impl RetentionConfig {
+    fn validate(&self) -> Result<()> {
+        if self.interval_secs == 0 {
+            bail!("retention.interval_secs must be at least 1");
+        }
+        if self.max_samples_per_sensor == Some(0) {
+            bail!("retention.max_samples_per_sensor must be at least 1");
+        }
+        if self.max_bytes == Some(0) {
+            bail!("retention.max_bytes must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Conditions checked by `GET /readyz`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HealthConfig {
+    /// Not ready when the last SensorStatus sample is older than this. Unset by default,
+    /// since sensors may legitimately stay silent.
+    pub(crate) max_sample_age_secs: Option<u64>,
+}
+
+/// Keeps sensors on the config last written through the gateway.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ReconcileConfig {
+    pub(crate) enabled: bool,
+    /// Seconds between two comparisons of desired config and reported status
+    pub(crate) interval_secs: u64,
+    /// A drifted config is not re-sent more often than this
+    pub(crate) min_resend_interval_secs: u64,
+    /// Write every desired config once when the gateway starts
+    pub(crate) republish_on_startup: bool,
+}
+
+impl Default for ReconcileConfig {
+    fn default() -> Self {
+        ReconcileConfig {
+            enabled: true,
+            interval_secs: 30,
+            min_resend_interval_secs: 30,
+            republish_on_startup: true,
+        }
+    }
+}
+
+impl ReconcileConfig {
+    fn validate(&self) -> Result<()> {
+        if self.interval_secs == 0 {
+            bail!("reconcile.interval_secs must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Task writing the SensorConfig topic on behalf of every request.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WriterConfig {
+    /// Writes waiting for the writer task. Further requests wait for a free slot
+    pub(crate) queue_capacity: usize,
+    /// A request fails with 504 when its write is not done within this time, acknowledgments
+    /// requested with `ack=true` are awaited on top
+    pub(crate) timeout_ms: u64,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        WriterConfig {
+            queue_capacity: 64,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl WriterConfig {
+    fn validate(&self) -> Result<()> {
+        if self.queue_capacity == 0 {
+            bail!("writer.queue_capacity must be at least 1");
+        }
+        if self.timeout_ms == 0 {
+            bail!("writer.timeout_ms must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Rules a SensorConfig has to pass before it is published.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ValidationConfig {
+    /// Reject configs of sensor types without a capability profile
+    pub(crate) require_profile: bool,
+    /// Capability profiles by sensor type
+    pub(crate) capabilities: HashMap<String, CapabilityProfile>,
+}
+
+/// What a sensor type can be configured to. Limits left out are not checked.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CapabilityProfile {
+    /// The frequency must fall into one of these bands
+    pub(crate) frequency_bands: Vec<ValueRange>,
+    pub(crate) max_power: Option<u32>,
+    /// Allowed squelti values
+    pub(crate) squelch: Option<ValueRange>,
+}
+
+/// Inclusive range of values.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ValueRange {
+    pub(crate) min: u32,
+    pub(crate) max: u32,
+}
+
+impl ValueRange {
+    pub(crate) fn contains(&self, value: u32) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+impl ValidationConfig {
+    fn validate(&self) -> Result<()> {
+        for (sensor_type, profile) in &self.capabilities {
+            let ranges = profile
+                .frequency_bands
+                .iter()
+                .map(|band| ("frequency_bands", band))
+                .chain(profile.squelch.iter().map(|squelch| ("squelch", squelch)));
+            for (field, range) in ranges {
+                if range.min > range.max {
+                    bail!(
+                        "validation.capabilities.{sensor_type}.{field}: min {} is above max {}",
+                        range.min,
+                        range.max
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Named set of QoS policies. Policies left out keep the rustdds defaults.
+/// Also used to report the effective QoS of the created DDS entities.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct QosProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reliability: Option<ReliabilityConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) durability: Option<DurabilityConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) history: Option<HistoryConfig>,
+    /// Maximum period between two samples of an instance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) deadline_ms: Option<u64>,
+    /// How long a written sample stays valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) lifespan_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) liveliness: Option<LivelinessConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) ownership: Option<OwnershipConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum ReliabilityConfig {
+    BestEffort,
+    Reliable {
+        #[serde(default = "default_max_blocking_time_ms")]
+        max_blocking_time_ms: u64,
+    },
+}
+
+fn default_max_blocking_time_ms() -> u64 {
+    100
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DurabilityConfig {
+    Volatile,
+    TransientLocal,
+    Transient,
+    Persistent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum HistoryConfig {
+    KeepLast { depth: i32 },
+    KeepAll,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum LivelinessConfig {
+    Automatic { lease_duration_ms: u64 },
+    ManualByParticipant { lease_duration_ms: u64 },
+    ManualByTopic { lease_duration_ms: u64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum OwnershipConfig {
+    Shared,
+    Exclusive { strength: i32 },
+}
+
+/// Longest duration a rustdds Duration can hold, in milliseconds.
+const MAX_DURATION_MS: u64 = i32::MAX as u64 * 1000;
+
+fn duration(ms: u64) -> rustdds::Duration {
+    rustdds::Duration::from_millis(ms as i64)
+}
+
+fn millis(duration: rustdds::Duration) -> u64 {
+    duration.to_std().as_millis().min(MAX_DURATION_MS as u128) as u64
+}
+
+impl QosProfile {
+    pub(crate) fn build(&self) -> QosPolicies {
+        let mut builder = QosPolicyBuilder::new();
+        if let Some(reliability) = self.reliability {
+            builder = builder.reliability(match reliability {
+                ReliabilityConfig::BestEffort => Reliability::BestEffort,
+                ReliabilityConfig::Reliable {
+                    max_blocking_time_ms,
+                } => Reliability::Reliable {
+                    max_blocking_time: duration(max_blocking_time_ms),
+                },
+            });
+        }
+        if let Some(durability) = self.durability {
+            builder = builder.durability(match durability {
+                DurabilityConfig::Volatile => Durability::Volatile,
+                DurabilityConfig::TransientLocal => Durability::TransientLocal,
+                DurabilityConfig::Transient => Durability::Transient,
+                DurabilityConfig::Persistent => Durability::Persistent,
+            });
+        }
+        if let Some(history) = self.history {
+            builder = builder.history(match history {
+                HistoryConfig::KeepLast { depth } => History::KeepLast { depth },
+                HistoryConfig::KeepAll => History::KeepAll,
+            });
+        }
+        if let Some(deadline_ms) = self.deadline_ms {
+            builder = builder.deadline(Deadline(duration(deadline_ms)));
+        }
+        if let Some(lifespan_ms) = self.lifespan_ms {
+            builder = builder.lifespan(Lifespan {
+                duration: duration(lifespan_ms),
+            });
+        }
+        if let Some(liveliness) = self.liveliness {
+            builder = builder.liveliness(match liveliness {
+                LivelinessConfig::Automatic { lease_duration_ms } => Liveliness::Automatic {
+                    lease_duration: duration(lease_duration_ms),
+                },
+                LivelinessConfig::ManualByParticipant { lease_duration_ms } => {
+                    Liveliness::ManualByParticipant {
+                        lease_duration: duration(lease_duration_ms),
+                    }
+                }
+                LivelinessConfig::ManualByTopic { lease_duration_ms } => Liveliness::ManualByTopic {
+                    lease_duration: duration(lease_duration_ms),
+                },
+            });
+        }
+        if let Some(ownership) = self.ownership {
+            builder = builder.ownership(match ownership {
+                OwnershipConfig::Shared => Ownership::Shared,
+                OwnershipConfig::Exclusive { strength } => Ownership::Exclusive { strength },
+            });
+        }
+        builder.build()
+    }
+
+    fn validate(&self, name: &str) -> Result<()> {
+        if let Some(HistoryConfig::KeepLast { depth }) = self.history {
+            if depth < 1 {
+                bail!("qos profile \"{name}\": history depth must be at least 1, got {depth}");
+            }
+        }
+        let durations = [
+            (
+                "reliability.max_blocking_time_ms",
+                match self.reliability {
+                    Some(ReliabilityConfig::Reliable {
+                        max_blocking_time_ms,
+                    }) => Some(max_blocking_time_ms),
+                    _ => None,
+                },
+            ),
+            ("deadline_ms", self.deadline_ms),
+            ("lifespan_ms", self.lifespan_ms),
+            (
+                "liveliness.lease_duration_ms",
+                self.liveliness.map(|liveliness| match liveliness {
+                    LivelinessConfig::Automatic { lease_duration_ms }
+                    | LivelinessConfig::ManualByParticipant { lease_duration_ms }
+                    | LivelinessConfig::ManualByTopic { lease_duration_ms } => lease_duration_ms,
+                }),
+            ),
+        ];
+        for (field, ms) in durations {
+            if ms.is_some_and(|ms| ms > MAX_DURATION_MS) {
+                bail!("qos profile \"{name}\": {field} must be at most {MAX_DURATION_MS}");
+            }
+        }
+        if let Some(lifespan_ms) = self.lifespan_ms {
+            if lifespan_ms == 0 {
+                bail!("qos profile \"{name}\": lifespan_ms must be at least 1");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&QosPolicies> for QosProfile {
+    fn from(qos: &QosPolicies) -> Self {
+        QosProfile {
+            reliability: qos.reliability().map(|reliability| match reliability {
+                Reliability::BestEffort => ReliabilityConfig::BestEffort,
+                Reliability::Reliable { max_blocking_time } => ReliabilityConfig::Reliable {
+                    max_blocking_time_ms: millis(max_blocking_time),
+                },
+            }),
+            durability: qos.durability().map(|durability| match durability {
+                Durability::Volatile => DurabilityConfig::Volatile,
+                Durability::TransientLocal => DurabilityConfig::TransientLocal,
+                Durability::Transient => DurabilityConfig::Transient,
+                Durability::Persistent => DurabilityConfig::Persistent,
+            }),
+            history: qos.history().map(|history| match history {
+                History::KeepLast { depth } => HistoryConfig::KeepLast { depth },
+                History::KeepAll => HistoryConfig::KeepAll,
+            }),
+            deadline_ms: qos.deadline().map(|Deadline(deadline)| millis(deadline)),
+            lifespan_ms: qos.lifespan().map(|lifespan| millis(lifespan.duration)),
+            liveliness: qos.liveliness().map(|liveliness| match liveliness {
+                Liveliness::Automatic { lease_duration } => LivelinessConfig::Automatic {
+                    lease_duration_ms: millis(lease_duration),
+                },
+                Liveliness::ManualByParticipant { lease_duration } => {
+                    LivelinessConfig::ManualByParticipant {
+                        lease_duration_ms: millis(lease_duration),
+                    }
+                }
+                Liveliness::ManualByTopic { lease_duration } => LivelinessConfig::ManualByTopic {
+                    lease_duration_ms: millis(lease_duration),
+                },
+            }),
+            ownership: qos.ownership().map(|ownership| match ownership {
+                Ownership::Shared => OwnershipConfig::Shared,
+                Ownership::Exclusive { strength } => OwnershipConfig::Exclusive { strength },
+            }),
+        }
+    }
+}
+
+impl GatewayConfig {
+    /// Loads the configuration file (if any), applies environment and command line overrides
+    /// and validates the result.
+    pub(crate) fn load(args: &Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("cannot read config file {}", path.display()))?;
+                toml::from_str::<GatewayConfig>(&text)
+                    .with_context(|| format!("invalid config file {}", path.display()))?
+            }
+            None => GatewayConfig::default(),
+        };
+        if let Some(dir) = args.config.as_ref().and_then(|path| path.parent()) {
+            for idl_file in &mut config.idl_files {
+                *idl_file = dir.join(&*idl_file);
+            }
+        }
+
+        if let Some(domain_id) = args.domain_id {
+            config.domain_id = domain_id;
+        }
+        if let Some(bind_address) = &args.bind_address {
+            config.bind_address = bind_address.clone();
+        }
+        if let Some(database_path) = &args.database_path {
+            config.database_path = Some(database_path.clone());
+        }
+        if let Some(name) = &args.sensor_config_topic {
+            config.topics.sensor_config.name = name.clone();
+        }
+        if let Some(name) = &args.sensor_status_topic {
+            config.topics.sensor_status.name = name.clone();
+        }
+        // a config file may define its own profiles without repeating the default one
+        config
+            .qos
+            .entry(DEFAULT_QOS_PROFILE.to_string())
+            .or_default();
+        config.idl = idl::load(&config.idl_files)?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.domain_id > MAX_DOMAIN_ID {
+            bail!(
+                "domain_id must be between 0 and {MAX_DOMAIN_ID}, got {}",
+                self.domain_id
+            );
+        }
+        self.validate_bind_address()?;
+        if self.database_path().as_os_str().is_empty() {
+            bail!("database_path must not be empty");
+        }
+
+        let topics = [
+            ("sensor_config", &self.topics.sensor_config, "sensors::SensorConfig"),
+            ("sensor_status", &self.topics.sensor_status, "sensors::SensorStatus"),
+        ];
+        for (key, topic, compiled) in topics {
+            if topic.name.trim().is_empty() {
+                bail!("topics.{key}.name must not be empty");
+            }
+            if let Some(idl_type) = &topic.idl_type {
+                self.validate_compiled_type(key, idl_type, compiled)?;
+            }
+            if !self.qos.contains_key(&topic.qos_profile) {
+                bail!(
+                    "topics.{key}.qos_profile refers to unknown qos profile \"{}\"",
+                    topic.qos_profile
+                );
+            }
+        }
+        if self.topics.sensor_config.name == self.topics.sensor_status.name {
+            bail!(
+                "topics.sensor_config.name and topics.sensor_status.name must differ, both are \"{}\"",
+                self.topics.sensor_config.name
+            );
+        }
+
+        for (name, profile) in &self.qos {
+            profile.validate(name)?;
+        }
+        self.retention.validate()?;
+        self.reconcile.validate()?;
+        self.writer.validate()?;
+        self.validation.validate()?;
+
+        if self.dynamic_topics.len() > MAX_DYNAMIC_TOPICS {
+            bail!(
+                "at most {MAX_DYNAMIC_TOPICS} dynamic_topics are supported, got {}",
+                self.dynamic_topics.len()
+            );
+        }
+        for (index, topic) in self.dynamic_topics.iter().enumerate() {
+            topic.validate(&self.idl)?;
+            if topic.name == self.topics.sensor_config.name
+                || topic.name == self.topics.sensor_status.name
+                || self.dynamic_topics[..index]
+                    .iter()
+                    .any(|other| other.name == topic.name)
+            {
+                bail!("dynamic topic \"{}\" is declared twice", topic.name);
+            }
+            if !self.qos.contains_key(&topic.qos_profile) {
+                bail!(
+                    "dynamic topic \"{}\" refers to unknown qos profile \"{}\"",
+                    topic.name,
+                    topic.qos_profile
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// SensorConfig and SensorStatus are compiled in, so their IDL can only be compared.
+    fn validate_compiled_type(&self, key: &str, idl_type: &str, compiled: &str) -> Result<()> {
+        let Some(fields) = self.idl.structs.get(idl_type.trim_start_matches("::")) else {
+            bail!("topics.{key}.idl_type {idl_type} is not a struct of idl_files");
+        };
+        let compiled = crate::sensor_layout(compiled).fields;
+        if *fields != compiled {
+            let describe = |fields: &[FieldLayout]| {
+                fields
+                    .iter()
+                    .map(|field| {
+                        let key = if field.key { "@key " } else { "" };
+                        format!("{key}{:?} {}", field.field_type, field.name)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            bail!(
+                "topics.{key}.idl_type {idl_type} does not match the compiled type: expected [{}], IDL declares [{}]",
+                describe(&compiled),
+                describe(fields)
+            );
+        }
+        Ok(())
+    }
+
+    fn validate_bind_address(&self) -> Result<()> {
+        if self.bind_address.parse::<SocketAddr>().is_ok() {
+            return Ok(());
+        }
+        // host names such as localhost:3000 are resolved when binding
+        match self.bind_address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+            _ => bail!(
+                "bind_address must be host:port, got \"{}\"",
+                self.bind_address
+            ),
+        }
+    }
+
+    pub(crate) fn database_path(&self) -> PathBuf {
+        self.database_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&self.topics.sensor_status.name))
+    }
+
+    pub(crate) fn topic_qos(&self, topic: &TopicConfig) -> QosPolicies {
+        // existence of the profile is checked in validate()
+        self.qos[&topic.qos_profile].build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Writes `files` into a fresh directory and loads its config.toml with `args`.
+    fn load_files(name: &str, files: &[(&str, &str)], args: impl FnOnce(PathBuf) -> Args) -> Result<GatewayConfig> {
+        let dir = std::env::temp_dir().join(format!("webdds-config-{}-{name}", std::process::id()));
+        for (file, text) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        let config = GatewayConfig::load(&args(dir.join("config.toml")));
+        std::fs::remove_dir_all(&dir).unwrap();
+        config
+    }
+
+    fn file_args(config: PathBuf) -> Args {
+        Args {
+            config: Some(config),
+            domain_id: None,
+            bind_address: None,
+            database_path: None,
+            sensor_config_topic: None,
+            sensor_status_topic: None,
+        }
+    }
+
+    fn load(name: &str, text: &str) -> Result<GatewayConfig> {
+        load_files(name, &[("config.toml", text)], file_args)
+    }
+
+    fn error(name: &str, text: &str) -> String {
+        load(name, text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn environment_overrides_file_and_command_line_overrides_both() {
+        // the only test reading WEBDDS_* variables, the others build Args directly
+        std::env::set_var("WEBDDS_DOMAIN_ID", "2");
+        std::env::set_var("WEBDDS_BIND_ADDRESS", "localhost:2000");
+        let config = load_files(
+            "precedence",
+            &[(
+                "config.toml",
+                "domain_id = 1\nbind_address = \"localhost:1000\"\ndatabase_path = \"file.db\"\n",
+            )],
+            |path| {
+                Args::try_parse_from([
+                    "gateway".as_ref(),
+                    "--config".as_ref(),
+                    path.as_os_str(),
+                    "--bind-address".as_ref(),
+                    "localhost:3000".as_ref(),
+                ])
+                .unwrap()
+            },
+        );
+        std::env::remove_var("WEBDDS_DOMAIN_ID");
+        std::env::remove_var("WEBDDS_BIND_ADDRESS");
+        let config = config.unwrap();
+        assert_eq!(config.domain_id, 2);
+        assert_eq!(config.bind_address, "localhost:3000");
+        assert_eq!(config.database_path, Some(PathBuf::from("file.db")));
+    }
+
+    #[test]
+    fn idl_files_are_relative_to_the_config_file() {
+        let mut config_dir = None;
+        let config = load_files(
+            "idl-files",
+            &[
+                ("config.toml", "idl_files = [\"idl/types.idl\"]\n"),
+                ("idl/types.idl", "module m { struct Reading { @key string id; }; };"),
+            ],
+            |path| {
+                config_dir = path.parent().map(Path::to_path_buf);
+                file_args(path)
+            },
+        )
+        .unwrap();
+        assert_eq!(config.idl_files, [config_dir.unwrap().join("idl/types.idl")]);
+        assert!(config.idl.structs.contains_key("m::Reading"));
+    }
+
+    #[test]
+    fn defaults_load_without_a_config_file() {
+        let config = GatewayConfig::load(&Args {
+            config: None,
+            ..file_args(PathBuf::new())
+        })
+        .unwrap();
+        assert_eq!(config.bind_address, "localhost:3000");
+        assert!(config.qos.contains_key(DEFAULT_QOS_PROFILE));
+    }
+
+    #[test]
+    fn rejects_unknown_qos_profiles() {
+        let message = error("unknown-qos", "[topics.sensor_config]\nname = \"SensorConfig\"\nqos_profile = \"command\"\n");
+        assert_eq!(message, "topics.sensor_config.qos_profile refers to unknown qos profile \"command\"");
+
+        let message = error(
+            "unknown-dynamic-qos",
+            "[[dynamic_topics]]\nname = \"Engine\"\nqos_profile = \"fast\"\nfields = [{ name = \"id\", type = \"u32\", key = true }]\n",
+        );
+        assert_eq!(message, "dynamic topic \"Engine\" refers to unknown qos profile \"fast\"");
+    }
+
+    #[test]
+    fn rejects_invalid_dynamic_topics() {
+        let cases = [
+            (
+                "fields = [{ name = \"id\", type = \"u32\" }]",
+                "dynamic topic \"Engine\": at least one field must have key = true",
+            ),
+            (
+                "fields = []",
+                "dynamic topic \"Engine\": fields or idl_type is required",
+            ),
+            (
+                "idl_type = \"m::Missing\"",
+                "dynamic topic \"Engine\": idl_type m::Missing is not a struct of idl_files",
+            ),
+            (
+                "fields = [{ name = \"id\", type = \"u32\", key = true }, { name = \"id\", type = \"f32\" }]",
+                "dynamic topic \"Engine\": field \"id\" is declared twice",
+            ),
+            (
+                "fields = [{ name = \"id\", type = \"u32\", key = true }, { name = \" \", type = \"f32\" }]",
+                "dynamic topic \"Engine\": field 1 has an empty name",
+            ),
+        ];
+        for (fields, expected) in cases {
+            let text = format!("[[dynamic_topics]]\nname = \"Engine\"\n{fields}\n");
+            assert_eq!(error("dynamic", &text), expected);
+        }
+
+        let message = error(
+            "dynamic-twice",
+            "[[dynamic_topics]]\nname = \"SensorStatus\"\nfields = [{ name = \"id\", type = \"u32\", key = true }]\n",
+        );
+        assert_eq!(message, "dynamic topic \"SensorStatus\" is declared twice");
+    }
+
+    #[test]
+    fn rejects_inverted_capability_ranges() {
+        let message = error(
+            "capabilities",
+            "[validation.capabilities.radar]\nfrequency_bands = [{ min = 2400, max = 2500 }, { min = 5850, max = 5150 }]\n",
+        );
+        assert_eq!(message, "validation.capabilities.radar.frequency_bands: min 5850 is above max 5150");
+
+        let message = error("squelch", "[validation.capabilities.radar]\nsquelch = { min = 10, max = 0 }\n");
+        assert_eq!(message, "validation.capabilities.radar.squelch: min 10 is above max 0");
+    }
+
+    #[test]
+    fn rejects_zero_writer_settings() {
+        assert_eq!(
+            error("queue-capacity", "[writer]\nqueue_capacity = 0\n"),
+            "writer.queue_capacity must be at least 1"
+        );
+        assert_eq!(
+            error("timeout", "[writer]\ntimeout_ms = 0\n"),
+            "writer.timeout_ms must be at least 1"
+        );
+        assert!(load("writer", "[writer]\nqueue_capacity = 1\ntimeout_ms = 1\n").is_ok());
+    }
+}
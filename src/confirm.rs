@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::queue::UnmatchedPolicy;
+use crate::status::StatusEvent;
+use crate::{SensorConfig, SensorStatus};
+
+pub(crate) const DEFAULT_WAIT_TIMEOUT_MS: u64 = 5000;
+pub(crate) const MAX_WAIT_TIMEOUT_MS: u64 = 60000;
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub(crate) struct ConfigWriteQuery {
+    /// Wait until the sensor reports a SensorStatus matching the written config
+    pub(crate) wait: Option<bool>,
+    /// Wait until every matched reader acknowledged the sample, needs a reliable QoS
+    pub(crate) ack: Option<bool>,
+    /// How long to wait for each of `ack` and `wait`, 5000 by default and at most 60000
+    pub(crate) timeout_ms: Option<u64>,
+    /// What to do when no sensor reader is matched, `send` by default
+    #[param(inline)]
+    pub(crate) unmatched: Option<UnmatchedPolicy>,
+}
+
+impl ConfigWriteQuery {
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.timeout_ms
+                .unwrap_or(DEFAULT_WAIT_TIMEOUT_MS)
+                .min(MAX_WAIT_TIMEOUT_MS),
+        )
+    }
+
+    pub(crate) fn wait_timeout(&self) -> Option<Duration> {
+        self.wait.unwrap_or(false).then(|| self.timeout())
+    }
+
+    pub(crate) fn ack_timeout(&self) -> Option<Duration> {
+        self.ack.unwrap_or(false).then(|| self.timeout())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConfirmationState {
+    /// The sensor reported the written values
+    Applied,
+    /// The sensor reported a status, but not yet with the written values
+    Pending,
+    /// The sensor did not report any status
+    TimedOut,
+}
+
+/// Outcome of waiting for a written SensorConfig to show up in SensorStatus.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct ConfigConfirmation {
+    pub(crate) state: ConfirmationState,
+    pub(crate) config: SensorConfig,
+    /// Matching status when applied, otherwise the last status reported during the wait
+    pub(crate) status: Option<SensorStatus>,
+    pub(crate) waited_ms: u64,
+    /// Present with `ack=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) acknowledgement: Option<Acknowledgement>,
+}
+
+/// Readers that acknowledged a written sample, by GUID. rustdds only tells whether all
+/// matched readers acknowledged, so on a timeout every reader matched at write time is
+/// listed in `timed_out`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct Acknowledgement {
+    pub(crate) acknowledged: Vec<String>,
+    pub(crate) timed_out: Vec<String>,
+    pub(crate) waited_ms: u64,
+}
+
+impl Acknowledgement {
+    pub(crate) fn complete(&self) -> bool {
+        self.timed_out.is_empty()
+    }
+}
+
+/// Written config with the acknowledgement of the matched readers, returned for `ack=true`.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct AcknowledgedConfig {
+    pub(crate) config: SensorConfig,
+    pub(crate) acknowledgement: Acknowledgement,
+}
+
+/// Waits for a status of the config's sensor that matches the config. `status_events` must be
+/// subscribed before the config is written so that a fast reply is not missed.
+pub(crate) async fn wait_for_status(
+    mut status_events: broadcast::Receiver<StatusEvent>,
+    config: SensorConfig,
+    timeout: Duration,
+) -> ConfigConfirmation {
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let mut last_status = None;
+    loop {
+        match tokio::time::timeout_at(deadline, status_events.recv()).await {
+            Ok(Ok(event)) => {
+                if event.disposed || event.status.sensor_type != config.sensor_type {
+                    continue;
+                }
+                if config.is_applied_by(&event.status) {
+                    return ConfigConfirmation {
+                        state: ConfirmationState::Applied,
+                        config,
+                        status: Some(event.status),
+                        waited_ms: started.elapsed().as_millis() as u64,
+                        acknowledgement: None,
+                    };
+                }
+                last_status = Some(event.status);
+            }
+            Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        }
+    }
+    ConfigConfirmation {
+        state: if last_status.is_some() {
+            ConfirmationState::Pending
+        } else {
+            ConfirmationState::TimedOut
+        },
+        config,
+        status: last_status,
+        waited_ms: started.elapsed().as_millis() as u64,
+        acknowledgement: None,
+    }
+}
@@ -0,0 +1,311 @@
+use axum::{extract::State, http::StatusCode, Json};
+use rustdds::policy::Reliability;
+use rustdds::qos::HasQoSPolicy;
+use rustdds::with_key::DataWriter;
+use rustdds::{
+    DomainParticipant, DomainParticipantBuilder, QosPolicies, QosPolicyBuilder, RTPSEntity,
+    Subscriber, Topic, TopicKind,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::config::{GatewayConfig, QosProfile};
+use crate::discovery::{self, Discovery};
+use crate::dynamic::{self, DynamicEntities, DynamicTopics};
+use crate::error::{ApiError, ErrorCode};
+use crate::history;
+use crate::metrics::Metrics;
+use crate::queue::{self, QueueSink};
+use crate::reconcile::{self, ReconcileSink};
+use crate::subscriber::{self, StatusSink};
+use crate::writer::{ConfigWriter, WriterHandle};
+use crate::{AppState, DataWriterState, SensorConfig};
+
+const CONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const CONNECT_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Effective QoS of one DDS entity created by the gateway.
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct EntityQos {
+    /// topic, publisher, subscriber, datawriter or datareader
+    pub(crate) kind: String,
+    pub(crate) topic: Option<String>,
+    /// Configured profile the QoS was built from
+    pub(crate) profile: Option<String>,
+    pub(crate) qos: QosProfile,
+}
+
+/// QoS of the created entities by entity name.
+#[derive(Clone, Default)]
+pub(crate) struct QosRegistry(Arc<RwLock<BTreeMap<String, EntityQos>>>);
+
+impl QosRegistry {
+    pub(crate) fn record(
+        &self,
+        name: &str,
+        kind: &str,
+        topic: Option<&str>,
+        profile: Option<&str>,
+        entity: &impl HasQoSPolicy,
+    ) {
+        self.record_qos(name, kind, topic, profile, &entity.qos());
+    }
+
+    pub(crate) fn record_qos(
+        &self,
+        name: &str,
+        kind: &str,
+        topic: Option<&str>,
+        profile: Option<&str>,
+        qos: &QosPolicies,
+    ) {
+        if let Ok(mut entities) = self.0.write() {
+            entities.insert(
+                name.to_string(),
+                EntityQos {
+                    kind: kind.to_string(),
+                    topic: topic.map(str::to_string),
+                    profile: profile.map(str::to_string),
+                    qos: qos.into(),
+                },
+            );
+        }
+    }
+
+    pub(crate) fn entities(&self) -> BTreeMap<String, EntityQos> {
+        self.0.read().map(|entities| entities.clone()).unwrap_or_default()
+    }
+
+    pub(crate) fn remove(&self, name: &str) {
+        if let Ok(mut entities) = self.0.write() {
+            entities.remove(name);
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/dds/qos",
+    responses(
+        (status = 200, body = BTreeMap<String, EntityQos>, description = "Get effective QoS of every DDS entity created by the gateway"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Internal error")
+    ),
+    tag = "get_handler_dds_qos"
+)]
+pub(crate) async fn get_handler_dds_qos(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<BTreeMap<String, EntityQos>>), ApiError> {
+    match state.qos.0.read() {
+        Ok(entities) => Ok((StatusCode::OK, Json(entities.clone()))),
+        Err(_) => Err(ApiError::new(ErrorCode::Internal, "QoS registry lock is poisoned")),
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DdsState {
+    /// No participant yet, the gateway serves stored data read-only
+    #[default]
+    Connecting,
+    Connected,
+}
+
+/// Progress of the DDS connection.
+#[derive(Serialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct DdsStatus {
+    pub(crate) state: DdsState,
+    /// Number of attempts to create the participant and its entities
+    pub(crate) attempts: u64,
+    pub(crate) last_error: Option<String>,
+    /// Time of the successful attempt in milliseconds since the Unix epoch
+    pub(crate) connected_at: Option<u64>,
+}
+
+/// Connection status and, once connected, the participant of the gateway.
+#[derive(Clone, Default)]
+pub(crate) struct DdsHandle {
+    status: Arc<RwLock<DdsStatus>>,
+    /// Keeps the participant alive for the lifetime of the gateway
+    participant: Arc<OnceLock<DomainParticipant>>,
+    subscriber: Arc<OnceLock<JoinHandle<()>>>,
+    pub(crate) discovery: Discovery,
+}
+
+impl DdsHandle {
+    pub(crate) fn status(&self) -> DdsStatus {
+        self.status.read().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Whether the SensorStatus subscriber task is running, `None` before DDS is connected.
+    pub(crate) fn subscriber_alive(&self) -> Option<bool> {
+        self.subscriber.get().map(|task| !task.is_finished())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut DdsStatus)) {
+        if let Ok(mut status) = self.status.write() {
+            f(&mut status);
+        }
+    }
+}
+
+/// Everything the connector installs once the DDS entities are created.
+pub(crate) struct Connector {
+    pub(crate) config: GatewayConfig,
+    pub(crate) handle: DdsHandle,
+    pub(crate) writer: DataWriterState,
+    pub(crate) dynamic: DynamicTopics,
+    pub(crate) qos_registry: QosRegistry,
+    pub(crate) metrics: Metrics,
+    /// Desired configs, recorded by the writer task
+    pub(crate) desired: sled::Tree,
+    pub(crate) status_sink: StatusSink,
+    pub(crate) reconcile_sink: ReconcileSink,
+    pub(crate) queue_sink: QueueSink,
+}
+
+struct Connection {
+    participant: DomainParticipant,
+    writer: DataWriter<SensorConfig>,
+    subscriber: Subscriber,
+    status_topic: Topic,
+    status_qos: QosPolicies,
+    dynamic: Vec<DynamicEntities>,
+}
+
+impl Connector {
+    fn connect(&self) -> anyhow::Result<Connection> {
+        let config = &self.config;
+        let participant = DomainParticipantBuilder::new(config.domain_id).build()?;
+        let qos = QosPolicyBuilder::new().build();
+        let config_topic = &config.topics.sensor_config;
+        let status_topic = &config.topics.sensor_status;
+        let config_topic_qos = config.topic_qos(config_topic);
+        let status_qos = config.topic_qos(status_topic);
+        let topic_sensor_config = participant.create_topic(
+            config_topic.name.clone(),
+            "Topic: SensorConfig".to_string(),
+            &config_topic_qos,
+            TopicKind::WithKey,
+        )?;
+        let topic_sensor_status = participant.create_topic(
+            status_topic.name.clone(),
+            "Topic: SensorStatus".to_string(),
+            &status_qos,
+            TopicKind::WithKey,
+        )?;
+        let publisher = participant.create_publisher(&qos)?;
+        let writer = publisher
+            .create_datawriter_cdr::<SensorConfig>(&topic_sensor_config, Some(config_topic_qos))?;
+        let subscriber = participant.create_subscriber(&qos)?;
+
+        let registry = &self.qos_registry;
+        registry.record(
+            &format!("topic/{}", config_topic.name),
+            "topic",
+            Some(&config_topic.name),
+            Some(&config_topic.qos_profile),
+            &topic_sensor_config,
+        );
+        registry.record(
+            &format!("topic/{}", status_topic.name),
+            "topic",
+            Some(&status_topic.name),
+            Some(&status_topic.qos_profile),
+            &topic_sensor_status,
+        );
+        registry.record_qos("publisher", "publisher", None, None, &qos);
+        registry.record_qos("subscriber", "subscriber", None, None, &qos);
+        registry.record(
+            &format!("datawriter/{}", config_topic.name),
+            "datawriter",
+            Some(&config_topic.name),
+            Some(&config_topic.qos_profile),
+            &writer,
+        );
+        self.handle
+            .discovery
+            .register(&format!("datawriter/{}", config_topic.name), writer.guid());
+
+        let dynamic = dynamic::connect(self, &participant, &publisher, &subscriber)?;
+        Ok(Connection {
+            participant,
+            writer,
+            subscriber,
+            status_topic: topic_sensor_status,
+            status_qos,
+            dynamic,
+        })
+    }
+
+    fn install(self, connection: Connection) {
+        tokio::spawn(discovery::run_discovery(
+            connection.participant.clone(),
+            self.handle.discovery.clone(),
+        ));
+        let _ = self.handle.participant.set(connection.participant);
+        let config_topic = &self.config.topics.sensor_config;
+        let reliable = matches!(
+            self.config.topic_qos(config_topic).reliability(),
+            Some(Reliability::Reliable { .. })
+        );
+        let writer = ConfigWriter::new(
+            connection.writer,
+            config_topic.name.clone(),
+            reliable,
+            self.metrics.clone(),
+            self.desired,
+            self.handle.discovery.clone(),
+        );
+        let _ = self.writer.set(WriterHandle::spawn(writer, &self.config.writer, self.metrics.clone()));
+        dynamic::install(&self.dynamic, connection.dynamic);
+
+        // subscribed before the subscriber starts so that no reappearing sensor is missed
+        let status_events = self.status_sink.status_events.subscribe();
+        let task = tokio::spawn(subscriber::run_subscriber(
+            connection.subscriber,
+            connection.status_topic,
+            connection.status_qos,
+            self.config.topics.sensor_status.clone(),
+            self.status_sink,
+        ));
+        let _ = self.handle.subscriber.set(task);
+        tokio::spawn(queue::run_queue(self.queue_sink));
+        if self.config.reconcile.enabled {
+            tokio::spawn(reconcile::run_reconciler(
+                self.reconcile_sink,
+                status_events,
+                self.config.reconcile.clone(),
+            ));
+        }
+    }
+}
+
+/// Creates the participant and every entity of the gateway, retrying with a backoff until
+/// it succeeds. Until then the HTTP server runs in degraded mode.
+pub(crate) async fn run_connector(connector: Connector) {
+    let mut backoff = CONNECT_BACKOFF_MIN;
+    loop {
+        connector.handle.update(|s| s.attempts += 1);
+        match connector.connect() {
+            Ok(connection) => {
+                println!("dds connected on domain {}", connector.config.domain_id);
+                connector.handle.update(|s| {
+                    s.state = DdsState::Connected;
+                    s.connected_at = Some(history::now_millis());
+                });
+                connector.install(connection);
+                return;
+            }
+            Err(e) => {
+                eprintln!("dds connect error: {e:#}, retrying in {backoff:?}");
+                connector.handle.update(|s| s.last_error = Some(format!("{e:#}")));
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(CONNECT_BACKOFF_MAX);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::extract::{Json, Path};
+use crate::history::now_millis;
+use crate::{AppState, SensorConfig};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum InstanceState {
+    /// The config was written and the instance is alive
+    Alive,
+    /// The instance was disposed on the SensorConfig topic
+    Disposed,
+}
+
+/// Config the gateway asked a sensor to apply, stored in the `desired` sled tree keyed by
+/// `sensor_type`.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct DesiredConfig {
+    pub(crate) sensor_type: String,
+    /// Last config written, none when the instance was removed before any write
+    pub(crate) config: Option<SensorConfig>,
+    pub(crate) instance_state: InstanceState,
+    /// Milliseconds since the Unix epoch
+    pub(crate) updated_at: u64,
+}
+
+pub(crate) fn record_config(tree: &sled::Tree, config: &SensorConfig) -> sled::Result<()> {
+    let desired = DesiredConfig {
+        sensor_type: config.sensor_type.clone(),
+        config: Some(config.clone()),
+        instance_state: InstanceState::Alive,
+        updated_at: now_millis(),
+    };
+    let json = serde_json::to_vec(&desired).expect("DesiredConfig serializes");
+    tree.insert(&desired.sensor_type, json)?;
+    Ok(())
+}
+
+pub(crate) fn load_desired(tree: &sled::Tree, sensor_type: &str) -> sled::Result<Option<DesiredConfig>> {
+    Ok(tree
+        .get(sensor_type)?
+        .and_then(|value| serde_json::from_slice(&value).ok()))
+}
+
+/// Puts back a desired config loaded before a write, or removes the entry when there was none.
+pub(crate) fn restore(tree: &sled::Tree, sensor_type: &str, previous: Option<&DesiredConfig>) -> sled::Result<()> {
+    match previous {
+        Some(desired) => {
+            let json = serde_json::to_vec(desired).expect("DesiredConfig serializes");
+            tree.insert(sensor_type, json)?;
+        }
+        None => {
+            tree.remove(sensor_type)?;
+        }
+    }
+    Ok(())
+}
+
+/// Desired configs of instances that are still alive.
+pub(crate) fn load_alive(tree: &sled::Tree) -> sled::Result<Vec<SensorConfig>> {
+    let mut configs = vec![];
+    for item in tree.iter() {
+        let (_, value) = item?;
+        if let Ok(DesiredConfig {
+            config: Some(config),
+            instance_state: InstanceState::Alive,
+            ..
+        }) = serde_json::from_slice::<DesiredConfig>(&value)
+        {
+            configs.push(config);
+        }
+    }
+    Ok(configs)
+}
+
+/// Sets the instance state, keeping the last written config.
+pub(crate) fn record_instance_state(
+    tree: &sled::Tree,
+    sensor_type: &str,
+    instance_state: InstanceState,
+) -> sled::Result<DesiredConfig> {
+    let updated = tree.update_and_fetch(sensor_type, |old| {
+        let config = old
+            .and_then(|value| serde_json::from_slice::<DesiredConfig>(value).ok())
+            .and_then(|desired| desired.config);
+        let desired = DesiredConfig {
+            sensor_type: sensor_type.to_string(),
+            config,
+            instance_state,
+            updated_at: now_millis(),
+        };
+        Some(serde_json::to_vec(&desired).expect("DesiredConfig serializes"))
+    })?;
+    let value = updated.expect("update_and_fetch always stores a value");
+    Ok(serde_json::from_slice(&value).expect("stored above"))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sensor/config/{sensor_type}",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)")
+    ),
+    responses(
+        (status = 200, body = DesiredConfig, description = "Instance disposed"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed"),
+        (status = 502, body = Problem, content_type = "application/problem+json", description = "DDS dispose failed"),
+        (status = 503, body = Problem, content_type = "application/problem+json", description = "DDS is not connected yet"),
+        (status = 504, body = Problem, content_type = "application/problem+json", description = "The SensorConfig writer did not take the dispose within `writer.timeout_ms`")
+    ),
+    tag = "delete_handler_sensor_config"
+)]
+pub(crate) async fn delete_handler_sensor_config(
+    State(state): State<AppState>,
+    Path(sensor_type): Path<String>,
+) -> Result<(StatusCode, Json<DesiredConfig>), ApiError> {
+    let writer = state.writer.get().ok_or_else(ApiError::dds_unavailable)?;
+    let desired = writer.dispose(sensor_type).await?;
+    Ok((StatusCode::OK, Json(desired)))
+}
@@ -0,0 +1,371 @@
+use axum::{extract::State, http::StatusCode, Json};
+use rustdds::{
+    DomainParticipant, DomainParticipantStatusEvent, LostReason, StatusEvented, GUID,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::history;
+use crate::AppState;
+
+/// Number of participant liveliness changes kept for `GET /dds/liveliness`.
+const LIVELINESS_LOG_CAPACITY: usize = 256;
+
+/// What the participant of the gateway has discovered, kept up to date from its status events.
+#[derive(Clone, Default)]
+pub(crate) struct Discovery(Arc<RwLock<DiscoveryState>>);
+
+#[derive(Default)]
+struct DiscoveryState {
+    /// Entity name of each local reader and writer, as used by the QoS registry
+    locals: HashMap<GUID, String>,
+    /// Remote endpoints matched with each local endpoint
+    matched: HashMap<GUID, BTreeSet<GUID>>,
+    participants: BTreeMap<Prefix, RemoteParticipant>,
+    endpoints: BTreeMap<GUID, RemoteEndpoint>,
+    liveliness: VecDeque<LivelinessChange>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EndpointKind {
+    Reader,
+    Writer,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct RemoteEndpoint {
+    pub(crate) guid: String,
+    pub(crate) kind: EndpointKind,
+    pub(crate) topic: String,
+    pub(crate) type_name: String,
+    /// Milliseconds since the Unix epoch
+    pub(crate) discovered_at: u64,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct RemoteParticipant {
+    pub(crate) guid_prefix: String,
+    pub(crate) name: Option<String>,
+    pub(crate) lease_duration_ms: Option<u64>,
+    pub(crate) alive: bool,
+    /// Milliseconds since the Unix epoch of the latest discovery
+    pub(crate) discovered_at: u64,
+    pub(crate) lost_at: Option<u64>,
+    pub(crate) lost_reason: Option<String>,
+    /// Remote readers of the participant by topic
+    pub(crate) readers: BTreeMap<String, Vec<RemoteEndpoint>>,
+    /// Remote writers of the participant by topic
+    pub(crate) writers: BTreeMap<String, Vec<RemoteEndpoint>>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Liveliness {
+    Discovered,
+    Lost,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct LivelinessChange {
+    /// Milliseconds since the Unix epoch
+    pub(crate) at: u64,
+    pub(crate) guid_prefix: String,
+    pub(crate) name: Option<String>,
+    pub(crate) change: Liveliness,
+    pub(crate) reason: Option<String>,
+}
+
+/// A reader or writer of the gateway and the remote endpoints matched with it.
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct LocalEndpoint {
+    /// Entity name, e.g. `datawriter/SensorConfig`
+    pub(crate) name: String,
+    pub(crate) guid: String,
+    /// Matched remote endpoints. Those not announced by discovery yet only carry their GUID
+    pub(crate) matched: Vec<MatchedEndpoint>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct MatchedEndpoint {
+    pub(crate) guid: String,
+    pub(crate) participant: Option<String>,
+    pub(crate) topic: Option<String>,
+}
+
+/// GUID prefix, shared by a participant and all of its entities.
+type Prefix = [u8; 12];
+
+fn prefix_of(guid: &GUID) -> Prefix {
+    let mut prefix = Prefix::default();
+    prefix.copy_from_slice(&guid.to_bytes()[..12]);
+    prefix
+}
+
+pub(crate) fn guid_string(guid: &GUID) -> String {
+    let bytes = guid.to_bytes();
+    format!("{}:{}", hex(&bytes[..12]), hex(&bytes[12..]))
+}
+
+fn prefix_string(prefix: &Prefix) -> String {
+    hex(prefix)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn describe(reason: &LostReason) -> String {
+    match reason {
+        LostReason::Disposed => "disposed".to_string(),
+        LostReason::Timeout { lease, elapsed } => format!(
+            "lease of {:?} expired after {:?}",
+            lease.to_std(),
+            elapsed.to_std()
+        ),
+    }
+}
+
+impl DiscoveryState {
+    fn log(&mut self, prefix: &Prefix, change: Liveliness, reason: Option<String>) {
+        if self.liveliness.len() == LIVELINESS_LOG_CAPACITY {
+            self.liveliness.pop_front();
+        }
+        let name = self.participants.get(prefix).and_then(|p| p.name.clone());
+        self.liveliness.push_back(LivelinessChange {
+            at: history::now_millis(),
+            guid_prefix: prefix_string(prefix),
+            name,
+            change,
+            reason,
+        });
+    }
+
+    fn remove_remote(&mut self, remote: impl Fn(&GUID) -> bool) {
+        for remotes in self.matched.values_mut() {
+            remotes.retain(|guid| !remote(guid));
+        }
+        self.endpoints.retain(|guid, _| !remote(guid));
+    }
+
+    fn add_endpoint(&mut self, guid: GUID, kind: EndpointKind, topic: String, type_name: String) {
+        self.endpoints.insert(
+            guid,
+            RemoteEndpoint {
+                guid: guid_string(&guid),
+                kind,
+                topic,
+                type_name,
+                discovered_at: history::now_millis(),
+            },
+        );
+    }
+}
+
+impl Discovery {
+    /// Records the GUID of a local endpoint, replacing an earlier endpoint of the same name.
+    pub(crate) fn register(&self, name: &str, guid: GUID) {
+        if let Ok(mut state) = self.0.write() {
+            let replaced = state
+                .locals
+                .iter()
+                .filter(|(_, local)| local.as_str() == name)
+                .map(|(guid, _)| *guid)
+                .collect::<Vec<_>>();
+            for old in replaced {
+                state.locals.remove(&old);
+                state.matched.remove(&old);
+            }
+            state.locals.insert(guid, name.to_string());
+        }
+    }
+
+    /// Number of remote endpoints matched with the local endpoint `name`.
+    pub(crate) fn matched(&self, name: &str) -> usize {
+        self.matched_guids(name).len()
+    }
+
+    /// GUIDs of the remote endpoints matched with the local endpoint `name`. rustdds does not
+    /// implement `get_matched_subscriptions`, so matches are taken from the participant events.
+    pub(crate) fn matched_guids(&self, name: &str) -> Vec<String> {
+        let Ok(state) = self.0.read() else {
+            return vec![];
+        };
+        state
+            .locals
+            .iter()
+            .filter(|(_, local)| local.as_str() == name)
+            .filter_map(|(guid, _)| state.matched.get(guid))
+            .flatten()
+            .map(guid_string)
+            .collect()
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, DiscoveryState>, ApiError> {
+        self.0
+            .read()
+            .map_err(|_| ApiError::new(ErrorCode::Internal, "discovery state lock is poisoned"))
+    }
+
+    fn handle(&self, event: DomainParticipantStatusEvent) {
+        let Ok(mut state) = self.0.write() else {
+            return;
+        };
+        match event {
+            DomainParticipantStatusEvent::ParticipantDiscovered { dpd } => {
+                let prefix = prefix_of(&dpd.guid);
+                state.participants.insert(
+                    prefix,
+                    RemoteParticipant {
+                        guid_prefix: prefix_string(&prefix),
+                        name: dpd.entity_name,
+                        lease_duration_ms: dpd.lease_duration.map(|d| d.to_std().as_millis() as u64),
+                        alive: true,
+                        discovered_at: history::now_millis(),
+                        lost_at: None,
+                        lost_reason: None,
+                        readers: BTreeMap::new(),
+                        writers: BTreeMap::new(),
+                    },
+                );
+                state.log(&prefix, Liveliness::Discovered, None);
+            }
+            DomainParticipantStatusEvent::ParticipantLost { id, reason } => {
+                // the prefix type is not exported, so it is read through a GUID
+                let id = prefix_of(&GUID {
+                    prefix: id,
+                    ..GUID::GUID_UNKNOWN
+                });
+                let reason = describe(&reason);
+                if let Some(participant) = state.participants.get_mut(&id) {
+                    participant.alive = false;
+                    participant.lost_at = Some(history::now_millis());
+                    participant.lost_reason = Some(reason.clone());
+                }
+                state.remove_remote(|remote| prefix_of(remote) == id);
+                state.log(&id, Liveliness::Lost, Some(reason));
+            }
+            DomainParticipantStatusEvent::ReaderDetected { reader } => {
+                state.add_endpoint(reader.guid, EndpointKind::Reader, reader.topic_name, reader.type_name);
+            }
+            DomainParticipantStatusEvent::WriterDetected { writer } => {
+                state.add_endpoint(writer.guid, EndpointKind::Writer, writer.topic_name, writer.type_name);
+            }
+            DomainParticipantStatusEvent::RemoteReaderMatched {
+                local_writer,
+                remote_reader,
+            } => {
+                state.matched.entry(local_writer).or_default().insert(remote_reader);
+            }
+            DomainParticipantStatusEvent::RemoteWriterMatched {
+                local_reader,
+                remote_writer,
+            } => {
+                state.matched.entry(local_reader).or_default().insert(remote_writer);
+            }
+            DomainParticipantStatusEvent::ReaderLost { guid, .. }
+            | DomainParticipantStatusEvent::WriterLost { guid, .. } => {
+                state.remove_remote(|remote| *remote == guid);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Follows the status events of the participant for as long as it exists.
+pub(crate) async fn run_discovery(participant: DomainParticipant, discovery: Discovery) {
+    let listener = participant.status_listener();
+    let mut events = listener.as_async_status_stream();
+    while let Some(event) = events.next().await {
+        discovery.handle(event);
+    }
+    println!("participant status events ended");
+}
+
+#[utoipa::path(
+    get,
+    path = "/dds/participants",
+    responses(
+        (status = 200, body = [RemoteParticipant], description = "Get discovered remote participants with their readers and writers per topic. \
+            Lost participants are kept with `alive = false`"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Internal error")
+    ),
+    tag = "get_handler_dds_participants"
+)]
+pub(crate) async fn get_handler_dds_participants(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<RemoteParticipant>>), ApiError> {
+    let discovery = state.dds.discovery.read()?;
+    let mut participants = discovery.participants.clone();
+    for (guid, endpoint) in &discovery.endpoints {
+        let Some(participant) = participants.get_mut(&prefix_of(guid)) else {
+            continue;
+        };
+        let by_topic = match endpoint.kind {
+            EndpointKind::Reader => &mut participant.readers,
+            EndpointKind::Writer => &mut participant.writers,
+        };
+        by_topic.entry(endpoint.topic.clone()).or_default().push(endpoint.clone());
+    }
+    Ok((StatusCode::OK, Json(participants.into_values().collect())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/dds/matched",
+    responses(
+        (status = 200, body = [LocalEndpoint], description = "Get the readers and writers of the gateway, e.g. \
+            `datawriter/SensorConfig` and `datareader/SensorStatus`, with their matched remote endpoints"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Internal error")
+    ),
+    tag = "get_handler_dds_matched"
+)]
+pub(crate) async fn get_handler_dds_matched(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<LocalEndpoint>>), ApiError> {
+    let discovery = state.dds.discovery.read()?;
+    let mut locals = discovery
+        .locals
+        .iter()
+        .map(|(guid, name)| LocalEndpoint {
+            name: name.clone(),
+            guid: guid_string(guid),
+            matched: discovery
+                .matched
+                .get(guid)
+                .into_iter()
+                .flatten()
+                .map(|remote| MatchedEndpoint {
+                    guid: guid_string(remote),
+                    participant: discovery
+                        .participants
+                        .get(&prefix_of(remote))
+                        .map(|p| p.name.clone().unwrap_or_else(|| p.guid_prefix.clone())),
+                    topic: discovery.endpoints.get(remote).map(|e| e.topic.clone()),
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    locals.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((StatusCode::OK, Json(locals)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/dds/liveliness",
+    responses(
+        (status = 200, body = [LivelinessChange], description = "Get the latest participant discoveries and losses, oldest first"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Internal error")
+    ),
+    tag = "get_handler_dds_liveliness"
+)]
+pub(crate) async fn get_handler_dds_liveliness(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<LivelinessChange>>), ApiError> {
+    let discovery = state.dds.discovery.read()?;
+    Ok((StatusCode::OK, Json(discovery.liveliness.iter().cloned().collect())))
+}
@@ -0,0 +1,120 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::desired::{DesiredConfig, InstanceState};
+use crate::history::{history_prefix, now_millis, HistoryEntry};
+use crate::status::{self, StoredStatus};
+use crate::{AppState, SensorConfig, SensorStatus};
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct FieldDrift {
+    pub(crate) field: String,
+    /// Value of the last written SensorConfig
+    pub(crate) expected: u32,
+    /// Value of the latest SensorStatus
+    pub(crate) actual: u32,
+}
+
+/// Sensor whose reported status differs from the config last written through the gateway.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct SensorDrift {
+    pub(crate) sensor_type: String,
+    pub(crate) config: SensorConfig,
+    pub(crate) status: SensorStatus,
+    pub(crate) fields: Vec<FieldDrift>,
+    /// Start of the drift in milliseconds since the Unix epoch: the write when the sensor never
+    /// reported the config, otherwise the first differing status after the last matching one
+    pub(crate) drift_since: u64,
+    pub(crate) drift_duration_ms: u64,
+}
+
+pub(crate) fn field_drifts(config: &SensorConfig, status: &SensorStatus) -> Vec<FieldDrift> {
+    [
+        ("frequency", config.frequency, status.frequency),
+        ("power", config.power, status.power),
+        ("squelti", config.squelti, status.squelti),
+    ]
+    .into_iter()
+    .filter(|(_, expected, actual)| expected != actual)
+    .map(|(field, expected, actual)| FieldDrift {
+        field: field.to_string(),
+        expected,
+        actual,
+    })
+    .collect()
+}
+
+/// Walks the history of the sensor back to the write of the config.
+fn drift_since(history: &sled::Tree, config: &SensorConfig, written_at: u64) -> sled::Result<u64> {
+    let mut oldest_mismatch = None;
+    for item in history.scan_prefix(history_prefix(&config.sensor_type)).rev() {
+        let (_, value) = item?;
+        let Ok(entry) = serde_json::from_slice::<HistoryEntry>(&value) else {
+            continue;
+        };
+        if entry.received_at < written_at {
+            break;
+        }
+        if config.is_applied_by(&entry.status) {
+            return Ok(oldest_mismatch.unwrap_or(entry.received_at));
+        }
+        oldest_mismatch = Some(entry.received_at);
+    }
+    Ok(written_at)
+}
+
+fn load_drifts(state: &AppState) -> sled::Result<Vec<SensorDrift>> {
+    let now = now_millis();
+    let mut drifts = vec![];
+    for item in state.desired.iter() {
+        let (_, value) = item?;
+        let Ok(DesiredConfig {
+            config: Some(config),
+            instance_state: InstanceState::Alive,
+            updated_at,
+            ..
+        }) = serde_json::from_slice::<DesiredConfig>(&value)
+        else {
+            continue;
+        };
+        let Some(StoredStatus {
+            disposed: false,
+            status,
+            ..
+        }) = status::load_status(&state.status, &config.sensor_type)?
+        else {
+            continue;
+        };
+        let fields = field_drifts(&config, &status);
+        if fields.is_empty() {
+            continue;
+        }
+        let drift_since = drift_since(&state.history, &config, updated_at)?;
+        drifts.push(SensorDrift {
+            sensor_type: config.sensor_type.clone(),
+            config,
+            status,
+            fields,
+            drift_since,
+            drift_duration_ms: now.saturating_sub(drift_since),
+        });
+    }
+    Ok(drifts)
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/drift",
+    responses(
+        (status = 200, body = [SensorDrift], description = "Sensors whose status differs from the last written config"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_sensor_drift"
+)]
+pub(crate) async fn get_handler_sensor_drift(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<SensorDrift>>), ApiError> {
+    Ok((StatusCode::OK, Json(load_drifts(&state)?)))
+}
@@ -0,0 +1,545 @@
+use axum::{extract::State, http::StatusCode};
+use rustdds::with_key::{DataWriter, Sample};
+use cdr_encoding_size::CdrEncodingMaxSize;
+use rustdds::{
+    CdrEncodingSize, DomainParticipant, Key, Keyed, Publisher, QosPolicies, RTPSEntity, Subscriber,
+    TopicKind,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Mutex;
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use crate::config::{DynamicTopicConfig, GatewayConfig, TopicDirection};
+use crate::dds::Connector;
+use crate::error::{ApiError, ErrorCode};
+use crate::extract::{Json, Path};
+use crate::layout::TypeLayout;
+use crate::subscriber::{RESTART_BACKOFF_MAX, RESTART_BACKOFF_MIN};
+use crate::AppState;
+
+/// Number of topics that can be declared in configuration. Each one is bound to a
+/// `DynamicSample<SLOT>` type, so the limit is fixed at compile time.
+pub(crate) const MAX_DYNAMIC_TOPICS: usize = 8;
+
+/// Layouts by slot, set once at startup before any DataReader or DataWriter is created.
+static LAYOUTS: OnceLock<Vec<Arc<TypeLayout>>> = OnceLock::new();
+
+fn layout(slot: usize) -> &'static TypeLayout {
+    &LAYOUTS.get().expect("dynamic layouts are set at startup")[slot]
+}
+
+/// Sample of the dynamic topic bound to `SLOT`. Values are checked against the layout
+/// before a sample is built, so serialization only converts them to their CDR types.
+#[derive(Clone, Debug)]
+pub(crate) struct DynamicSample<const SLOT: usize> {
+    values: Vec<Value>,
+}
+
+impl<const SLOT: usize> Keyed for DynamicSample<SLOT> {
+    type K = DynamicKey<SLOT>;
+    fn key(&self) -> Self::K {
+        DynamicKey {
+            values: layout(SLOT).key_values(&self.values),
+        }
+    }
+}
+
+/// Key of `DynamicSample<SLOT>`: the values of the `@key` fields, encoded in CDR as a struct
+/// of only those fields so that the key hash matches other DDS implementations.
+#[derive(Clone, Debug)]
+pub(crate) struct DynamicKey<const SLOT: usize> {
+    values: Vec<Value>,
+}
+
+impl<const SLOT: usize> DynamicKey<SLOT> {
+    /// Instance name under which the latest sample is stored.
+    fn name(&self) -> String {
+        layout(SLOT).instance_name(&self.values)
+    }
+}
+
+// compared by instance name since JSON values have no order
+impl<const SLOT: usize> PartialEq for DynamicKey<SLOT> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl<const SLOT: usize> Eq for DynamicKey<SLOT> {}
+
+impl<const SLOT: usize> PartialOrd for DynamicKey<SLOT> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const SLOT: usize> Ord for DynamicKey<SLOT> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name().cmp(&other.name())
+    }
+}
+
+impl<const SLOT: usize> Hash for DynamicKey<SLOT> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name().hash(state)
+    }
+}
+
+impl<const SLOT: usize> Key for DynamicKey<SLOT> {}
+
+impl<const SLOT: usize> CdrEncodingSize for DynamicKey<SLOT> {
+    fn cdr_encoding_max_size() -> CdrEncodingMaxSize {
+        layout(SLOT).key_max_size()
+    }
+}
+
+impl<const SLOT: usize> Serialize for DynamicKey<SLOT> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        layout(SLOT).serialize_key(&self.values, serializer)
+    }
+}
+
+impl<'de, const SLOT: usize> Deserialize<'de> for DynamicKey<SLOT> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        layout(SLOT)
+            .deserialize_key(deserializer)
+            .map(|values| DynamicKey { values })
+    }
+}
+
+impl<const SLOT: usize> Serialize for DynamicSample<SLOT> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        layout(SLOT).serialize_values(&self.values, serializer)
+    }
+}
+
+impl<'de, const SLOT: usize> Deserialize<'de> for DynamicSample<SLOT> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        layout(SLOT)
+            .deserialize_values(deserializer)
+            .map(|values| DynamicSample { values })
+    }
+}
+
+
+type WriteFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// Type-erased DataWriter of a dynamic topic.
+pub(crate) trait DynamicWriter: Send + Sync {
+    fn write(&self, values: Vec<Value>) -> WriteFuture<'_>;
+}
+
+impl<const SLOT: usize> DynamicWriter for Mutex<DataWriter<DynamicSample<SLOT>>> {
+    fn write(&self, values: Vec<Value>) -> WriteFuture<'_> {
+        Box::pin(async move {
+            let writer = self.lock().await;
+            writer
+                .async_write(DynamicSample::<SLOT> { values }, None)
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+}
+
+/// Topic declared in configuration and bridged by the gateway.
+pub(crate) struct DynamicTopic {
+    slot: usize,
+    pub(crate) layout: Arc<TypeLayout>,
+    pub(crate) publish: bool,
+    /// Set once DDS is connected
+    pub(crate) writer: OnceLock<Arc<dyn DynamicWriter>>,
+    /// Latest sample per key, present when the topic is subscribed
+    pub(crate) samples: Option<sled::Tree>,
+}
+
+pub(crate) type DynamicTopics = Arc<BTreeMap<String, DynamicTopic>>;
+
+type ReaderTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// DDS entities of one topic, created by `connect` and handed to `install`.
+pub(crate) struct DynamicEntities {
+    name: String,
+    writer: Option<Arc<dyn DynamicWriter>>,
+    reader: Option<ReaderTask>,
+}
+
+/// Calls `$f::<SLOT>` for a slot number known only at runtime.
+macro_rules! with_slot {
+    ($slot:expr, $f:ident, $($arg:expr),*) => {
+        match $slot {
+            0 => $f::<0>($($arg),*),
+            1 => $f::<1>($($arg),*),
+            2 => $f::<2>($($arg),*),
+            3 => $f::<3>($($arg),*),
+            4 => $f::<4>($($arg),*),
+            5 => $f::<5>($($arg),*),
+            6 => $f::<6>($($arg),*),
+            7 => $f::<7>($($arg),*),
+            _ => unreachable!("at most MAX_DYNAMIC_TOPICS topics pass validation"),
+        }
+    };
+}
+
+/// Sets up the layouts and sample trees of every declared topic. Their DDS entities are
+/// created later by `connect`, so stored samples are served while DDS is unavailable.
+pub(crate) fn prepare(config: &GatewayConfig, db: &sled::Db) -> anyhow::Result<DynamicTopics> {
+    let topics = &config.dynamic_topics;
+    let layouts = topics
+        .iter()
+        .map(|topic| Arc::new(topic.layout(&config.idl)))
+        .collect::<Vec<_>>();
+    if LAYOUTS.set(layouts.clone()).is_err() {
+        anyhow::bail!("dynamic topics are already prepared");
+    }
+
+    let mut bridged = BTreeMap::new();
+    for (slot, (topic, layout)) in topics.iter().zip(layouts).enumerate() {
+        let samples = if topic.direction != TopicDirection::Publish {
+            Some(db.open_tree(format!("topic/{}", topic.name))?)
+        } else {
+            None
+        };
+        bridged.insert(
+            topic.name.clone(),
+            DynamicTopic {
+                slot,
+                layout,
+                publish: topic.direction != TopicDirection::Subscribe,
+                writer: OnceLock::new(),
+                samples,
+            },
+        );
+    }
+    Ok(Arc::new(bridged))
+}
+
+/// Creates the topics, writers and readers of every declared topic.
+pub(crate) fn connect(
+    connector: &Connector,
+    participant: &DomainParticipant,
+    publisher: &Publisher,
+    subscriber: &Subscriber,
+) -> anyhow::Result<Vec<DynamicEntities>> {
+    let config = &connector.config;
+    let topics = &connector.dynamic;
+    let mut entities = vec![];
+    for topic in &config.dynamic_topics {
+        let bridged = &topics[&topic.name];
+        // existence of the profile is checked when the config is loaded
+        let topic_qos = config.qos[&topic.qos_profile].build();
+        let dds_topic = participant.create_topic(
+            topic.name.clone(),
+            bridged.layout.type_name.clone(),
+            &topic_qos,
+            TopicKind::WithKey,
+        )?;
+        connector.qos_registry.record(
+            &format!("topic/{}", topic.name),
+            "topic",
+            Some(&topic.name),
+            Some(&topic.qos_profile),
+            &dds_topic,
+        );
+
+        let writer = if bridged.publish {
+            Some(with_slot!(
+                bridged.slot,
+                create_writer,
+                connector,
+                publisher,
+                &dds_topic,
+                &topic_qos,
+                topic
+            )?)
+        } else {
+            None
+        };
+        let reader = match &bridged.samples {
+            Some(tree) => Some(with_slot!(
+                bridged.slot,
+                reader_task,
+                connector,
+                subscriber,
+                &dds_topic,
+                &topic_qos,
+                topic,
+                tree.clone()
+            )),
+            None => None,
+        };
+        entities.push(DynamicEntities {
+            name: topic.name.clone(),
+            writer,
+            reader,
+        });
+    }
+    Ok(entities)
+}
+
+/// Makes the writers available to the handlers and starts the readers.
+pub(crate) fn install(topics: &DynamicTopics, entities: Vec<DynamicEntities>) {
+    for entity in entities {
+        if let Some(writer) = entity.writer {
+            let _ = topics[&entity.name].writer.set(writer);
+        }
+        if let Some(reader) = entity.reader {
+            tokio::spawn(reader);
+        }
+    }
+}
+
+fn create_writer<const SLOT: usize>(
+    connector: &Connector,
+    publisher: &Publisher,
+    dds_topic: &rustdds::Topic,
+    qos: &QosPolicies,
+    topic: &DynamicTopicConfig,
+) -> anyhow::Result<Arc<dyn DynamicWriter>> {
+    let writer = publisher.create_datawriter_cdr::<DynamicSample<SLOT>>(dds_topic, Some(qos.clone()))?;
+    connector.qos_registry.record(
+        &format!("datawriter/{}", topic.name),
+        "datawriter",
+        Some(&topic.name),
+        Some(&topic.qos_profile),
+        &writer,
+    );
+    connector
+        .handle
+        .discovery
+        .register(&format!("datawriter/{}", topic.name), writer.guid());
+    Ok(Arc::new(Mutex::new(writer)))
+}
+
+/// Task reading the topic into its sample tree. Like the SensorStatus subscriber, the
+/// DataReader is recreated with a backoff whenever its stream ends or it cannot be created.
+fn reader_task<const SLOT: usize>(
+    connector: &Connector,
+    subscriber: &Subscriber,
+    dds_topic: &rustdds::Topic,
+    qos: &QosPolicies,
+    topic: &DynamicTopicConfig,
+    tree: sled::Tree,
+) -> ReaderTask {
+    let subscriber = subscriber.clone();
+    let dds_topic = dds_topic.clone();
+    let qos = qos.clone();
+    let topic = topic.clone();
+    let qos_registry = connector.qos_registry.clone();
+    let discovery = connector.handle.discovery.clone();
+    let metrics = connector.metrics.clone();
+    Box::pin(async move {
+        let name = topic.name.clone();
+        let entity_name = format!("datareader/{}", name);
+        let mut backoff = RESTART_BACKOFF_MIN;
+        let mut first = true;
+        loop {
+            if !first {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+            }
+            first = false;
+
+            let reader = match subscriber
+                .create_datareader_cdr::<DynamicSample<SLOT>>(&dds_topic, Some(qos.clone()))
+            {
+                Ok(reader) => reader,
+                Err(e) => {
+                    eprintln!("dynamic subscriber {} create error: {:?}", name, e);
+                    continue;
+                }
+            };
+            qos_registry.record(
+                &entity_name,
+                "datareader",
+                Some(&name),
+                Some(&topic.qos_profile),
+                &reader,
+            );
+            discovery.register(&entity_name, reader.guid());
+            let mut async_reader = reader.async_sample_stream();
+            println!("dynamic subscriber start: {}", name);
+            while let Some(result) = async_reader.next().await {
+                let stored = match result {
+                    Ok(Sample::Value(sample)) => {
+                        let key = sample.key().name();
+                        metrics.record_received(&name, &key);
+                        let json = layout(SLOT).values_to_json(&sample.values);
+                        tree.insert(key, json.to_string().as_bytes())
+                    }
+                    Ok(Sample::Dispose(key)) => tree.remove(key.name()),
+                    Err(e) => {
+                        eprintln!("dynamic subscriber {} read error: {:?}", name, e);
+                        continue;
+                    }
+                };
+                backoff = RESTART_BACKOFF_MIN;
+                if let Err(e) = stored {
+                    eprintln!("dynamic subscriber {} store error: {:?}", name, e);
+                }
+            }
+            eprintln!("dynamic subscriber {} stream ended, recreating reader", name);
+            qos_registry.remove(&entity_name);
+        }
+    })
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct DynamicTopicInfo {
+    pub(crate) name: String,
+    pub(crate) publish: bool,
+    pub(crate) subscribe: bool,
+    pub(crate) layout: TypeLayout,
+}
+
+#[utoipa::path(
+    get,
+    path = "/topics",
+    responses(
+        (status = 200, body = [DynamicTopicInfo], description = "Get topics declared in configuration"),
+    ),
+    tag = "get_handler_topics"
+)]
+pub(crate) async fn get_handler_topics(
+    State(state): State<AppState>,
+) -> (StatusCode, Json<Vec<DynamicTopicInfo>>) {
+    let topics = state
+        .dynamic
+        .iter()
+        .map(|(name, topic)| DynamicTopicInfo {
+            name: name.clone(),
+            publish: topic.publish,
+            subscribe: topic.samples.is_some(),
+            layout: topic.layout.as_ref().clone(),
+        })
+        .collect();
+    (StatusCode::OK, Json(topics))
+}
+
+fn find_topic<'a>(state: &'a AppState, topic: &str) -> Result<&'a DynamicTopic, ApiError> {
+    state
+        .dynamic
+        .get(topic)
+        .ok_or_else(|| ApiError::not_found(format!("topic {topic} is not declared")))
+}
+
+fn subscribed_samples<'a>(dynamic: &'a DynamicTopic, topic: &str) -> Result<&'a sled::Tree, ApiError> {
+    dynamic.samples.as_ref().ok_or_else(|| {
+        ApiError::new(
+            ErrorCode::MethodNotAllowed,
+            format!("topic {topic} is publish only"),
+        )
+    })
+}
+
+#[utoipa::path(
+    put,
+    path = "/topics/{topic}",
+    params(
+        ("topic" = String, Path, description = "Topic name")
+    ),
+    request_body = Object,
+    responses(
+        (status = 200, body = Object, description = "Publish sample"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Topic not declared"),
+        (status = 405, body = Problem, content_type = "application/problem+json", description = "Topic is subscribe only"),
+        (status = 422, body = Problem, content_type = "application/problem+json", description = "Sample does not match the topic layout"),
+        (status = 502, body = Problem, content_type = "application/problem+json", description = "DDS write failed"),
+        (status = 503, body = Problem, content_type = "application/problem+json", description = "DDS is not connected yet")
+    ),
+    tag = "put_handler_topic"
+)]
+pub(crate) async fn put_handler_topic(
+    State(state): State<AppState>,
+    Path(topic): Path<String>,
+    Json(payload): Json<Value>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let dynamic = find_topic(&state, &topic)?;
+    if !dynamic.publish {
+        return Err(ApiError::new(
+            ErrorCode::MethodNotAllowed,
+            format!("topic {topic} is subscribe only"),
+        ));
+    }
+    let Some(writer) = dynamic.writer.get() else {
+        return Err(ApiError::dds_unavailable());
+    };
+    let values = dynamic
+        .layout
+        .values_from_json(&payload)
+        .map_err(|e| ApiError::validation(e, vec![]))?;
+    let key = dynamic
+        .layout
+        .instance_name(&dynamic.layout.key_values(&values));
+    let written = writer.write(values.clone()).await;
+    state.metrics.record_write(&topic, &key, written.is_ok());
+    written.map_err(|e| ApiError::dds_write(format!("cannot write to {topic}: {e}")))?;
+    Ok((StatusCode::OK, Json(dynamic.layout.values_to_json(&values))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/topics/{topic}",
+    params(
+        ("topic" = String, Path, description = "Topic name")
+    ),
+    responses(
+        (status = 200, body = [Object], description = "Get latest sample of every instance"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Topic not declared"),
+        (status = 405, body = Problem, content_type = "application/problem+json", description = "Topic is publish only"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_topic"
+)]
+pub(crate) async fn get_handler_topic(
+    State(state): State<AppState>,
+    Path(topic): Path<String>,
+) -> Result<(StatusCode, Json<Vec<Value>>), ApiError> {
+    let samples = subscribed_samples(find_topic(&state, &topic)?, &topic)?;
+    let mut list = vec![];
+    for item in samples.iter() {
+        let (_, value) = item?;
+        if let Ok(sample) = serde_json::from_slice::<Value>(&value) {
+            list.push(sample);
+        }
+    }
+    Ok((StatusCode::OK, Json(list)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/topics/{topic}/{key}",
+    params(
+        ("topic" = String, Path, description = "Topic name"),
+        ("key" = String, Path, description = "Instance key: the value of a single key field, or the JSON array of the key field values")
+    ),
+    responses(
+        (status = 200, body = Object, description = "Get latest sample of an instance"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Topic not declared or instance not seen"),
+        (status = 405, body = Problem, content_type = "application/problem+json", description = "Topic is publish only"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_topic_instance"
+)]
+pub(crate) async fn get_handler_topic_instance(
+    State(state): State<AppState>,
+    Path((topic, key)): Path<(String, String)>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let samples = subscribed_samples(find_topic(&state, &topic)?, &topic)?;
+    let Some(value) = samples.get(&key)? else {
+        return Err(ApiError::not_found(format!("instance {key} of {topic} has not been received")));
+    };
+    let sample = serde_json::from_slice::<Value>(&value).map_err(|e| {
+        ApiError::new(
+            ErrorCode::StorageFailed,
+            format!("stored sample {key} of {topic} cannot be decoded: {e}"),
+        )
+    })?;
+    Ok((StatusCode::OK, Json(sample)))
+}
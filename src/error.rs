@@ -0,0 +1,230 @@
+use axum::{
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{header, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::validation::FieldError;
+
+pub(crate) const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+pub(crate) const DDS_UNAVAILABLE: &str = "DDS is not connected, the gateway serves stored data read-only";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorCode {
+    /// Writing or disposing a sample on DDS failed
+    DdsWriteFailed,
+    /// The gateway runs in degraded mode without a DDS participant
+    DdsUnavailable,
+    /// No remote reader is matched with the writer of the topic
+    NoMatchedReaders,
+    /// Reading or writing the sled database failed
+    StorageFailed,
+    NotFound,
+    /// The request body violates the rules for its type
+    ValidationFailed,
+    Timeout,
+    BadRequest,
+    Conflict,
+    MethodNotAllowed,
+    UnsupportedMediaType,
+    PayloadTooLarge,
+    /// Unexpected failure inside the gateway
+    Internal,
+}
+
+impl ErrorCode {
+    fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::DdsWriteFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::DdsUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::NoMatchedReaders => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::StorageFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            ErrorCode::DdsWriteFailed => "DDS write failed",
+            ErrorCode::DdsUnavailable => "DDS unavailable",
+            ErrorCode::NoMatchedReaders => "No matched readers",
+            ErrorCode::StorageFailed => "Storage failed",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::ValidationFailed => "Validation failed",
+            ErrorCode::Timeout => "Timed out",
+            ErrorCode::BadRequest => "Bad request",
+            ErrorCode::Conflict => "Conflict",
+            ErrorCode::MethodNotAllowed => "Method not allowed",
+            ErrorCode::UnsupportedMediaType => "Unsupported media type",
+            ErrorCode::PayloadTooLarge => "Payload too large",
+            ErrorCode::Internal => "Internal error",
+        }
+    }
+
+    fn slug(self) -> &'static str {
+        match self {
+            ErrorCode::DdsWriteFailed => "dds-write-failed",
+            ErrorCode::DdsUnavailable => "dds-unavailable",
+            ErrorCode::NoMatchedReaders => "no-matched-readers",
+            ErrorCode::StorageFailed => "storage-failed",
+            ErrorCode::NotFound => "not-found",
+            ErrorCode::ValidationFailed => "validation-failed",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::BadRequest => "bad-request",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::MethodNotAllowed => "method-not-allowed",
+            ErrorCode::UnsupportedMediaType => "unsupported-media-type",
+            ErrorCode::PayloadTooLarge => "payload-too-large",
+            ErrorCode::Internal => "internal",
+        }
+    }
+}
+
+/// RFC 7807 problem details, sent as `application/problem+json` by every failing endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct Problem {
+    /// URI identifying the problem type, e.g. `urn:webdds:problem:not-found`
+    #[serde(rename = "type")]
+    pub(crate) problem_type: String,
+    pub(crate) title: String,
+    pub(crate) status: u16,
+    pub(crate) detail: String,
+    pub(crate) code: ErrorCode,
+    /// Field level errors of a failed validation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<FieldError>,
+}
+
+/// Error of a request handler.
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    code: ErrorCode,
+    detail: String,
+    errors: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub(crate) fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            detail: detail.into(),
+            errors: vec![],
+        }
+    }
+
+    pub(crate) fn dds_write(detail: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::DdsWriteFailed, detail)
+    }
+
+    pub(crate) fn dds_unavailable() -> Self {
+        ApiError::new(ErrorCode::DdsUnavailable, DDS_UNAVAILABLE)
+    }
+
+    pub(crate) fn not_found(detail: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::NotFound, detail)
+    }
+
+    pub(crate) fn bad_request(detail: impl Into<String>) -> Self {
+        ApiError::new(ErrorCode::BadRequest, detail)
+    }
+
+    pub(crate) fn validation(detail: impl Into<String>, errors: Vec<FieldError>) -> Self {
+        ApiError {
+            code: ErrorCode::ValidationFailed,
+            detail: detail.into(),
+            errors,
+        }
+    }
+
+    pub(crate) fn problem(&self) -> Problem {
+        Problem {
+            problem_type: format!("urn:webdds:problem:{}", self.code.slug()),
+            title: self.code.title().to_string(),
+            status: self.code.status().as_u16(),
+            detail: self.detail.clone(),
+            code: self.code,
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+impl From<sled::Error> for ApiError {
+    fn from(e: sled::Error) -> Self {
+        ApiError::new(ErrorCode::StorageFailed, e.to_string())
+    }
+}
+
+// rejections of the extractors in crate::extract
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match rejection.status() {
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::ValidationFailed,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            _ => ErrorCode::BadRequest,
+        };
+        ApiError::new(code, rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::bad_request(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        let code = if rejection.status().is_server_error() {
+            ErrorCode::Internal
+        } else {
+            ErrorCode::BadRequest
+        };
+        ApiError::new(code, rejection.body_text())
+    }
+}
+
+/// Answers requests to unknown routes.
+pub(crate) async fn fallback(method: Method, uri: Uri) -> ApiError {
+    ApiError::not_found(format!("no route for {method} {}", uri.path()))
+}
+
+/// Axum answers a known path requested with another method with an empty 405, it gets a
+/// problem body here. Axum adds the `Allow` header afterwards.
+pub(crate) async fn method_not_allowed(method: Method, uri: Uri, response: Response) -> Response {
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.headers().contains_key(header::CONTENT_TYPE)
+    {
+        return response;
+    }
+    ApiError::new(
+        ErrorCode::MethodNotAllowed,
+        format!("{method} is not allowed on {}", uri.path()),
+    )
+    .into_response()
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.code.status(),
+            [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+            Json(self.problem()),
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,111 @@
+use axum::async_trait;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+/// `axum::Json` whose rejections are problem+json errors. Also used for JSON responses.
+pub(crate) struct Json<T>(pub(crate) T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for Json<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state).await?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// `axum::extract::Query` whose rejections are problem+json errors.
+pub(crate) struct Query<T>(pub(crate) T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for Query<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) = axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Query(value))
+    }
+}
+
+/// `axum::extract::Path` whose rejections are problem+json errors.
+pub(crate) struct Path<T>(pub(crate) T);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send, S: Send + Sync> FromRequestParts<S> for Path<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, StatusCode};
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    struct Sample {
+        value: u32,
+    }
+
+    fn problem_status(error: ApiError) -> (StatusCode, String) {
+        let response = error.into_response();
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        (response.status(), content_type)
+    }
+
+    async fn json_body(content_type: Option<&str>, body: &str) -> Result<Json<Sample>, ApiError> {
+        let mut request = Request::builder().method("PUT").uri("/");
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        Json::<Sample>::from_request(request.body(Body::from(body.to_string())).unwrap(), &()).await
+    }
+
+    #[tokio::test]
+    async fn json_rejections_are_problems() {
+        assert!(json_body(Some("application/json"), r#"{"value": 1}"#).await.is_ok());
+        let cases = [
+            (Some("application/json"), "{", StatusCode::BAD_REQUEST),
+            (Some("application/json"), r#"{"value": -1}"#, StatusCode::UNPROCESSABLE_ENTITY),
+            (None, r#"{"value": 1}"#, StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        ];
+        for (content_type, body, status) in cases {
+            let error = json_body(content_type, body).await.err().unwrap();
+            assert_eq!(problem_status(error), (status, "application/problem+json".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn query_rejections_are_problems() {
+        let (mut parts, _) = Request::builder().uri("/?value=abc").body(()).unwrap().into_parts();
+        let error = Query::<Sample>::from_request_parts(&mut parts, &()).await.err().unwrap();
+        assert_eq!(problem_status(error).0, StatusCode::BAD_REQUEST);
+
+        let (mut parts, _) = Request::builder().uri("/?value=7").body(()).unwrap().into_parts();
+        assert_eq!(Query::<Sample>::from_request_parts(&mut parts, &()).await.unwrap().0.value, 7);
+    }
+
+    #[tokio::test]
+    async fn path_without_parameters_is_an_internal_problem() {
+        let (mut parts, _) = Request::builder().uri("/").body(()).unwrap().into_parts();
+        let error = Path::<String>::from_request_parts(&mut parts, &()).await.err().unwrap();
+        assert_eq!(problem_status(error).0, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
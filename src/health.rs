@@ -0,0 +1,253 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::dds::{DdsState, DdsStatus};
+use crate::history;
+use crate::AppState;
+
+/// Written to prove that the database accepts writes.
+const DATABASE_PROBE_KEY: &[u8] = b"health/probe";
+/// The probe key is written at most this often, the checks in between only read it.
+const DATABASE_WRITE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Mode {
+    Normal,
+    /// DDS is not connected, stored data is served read-only and writes return 503
+    Degraded,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct SubscriberHealth {
+    /// Whether the SensorStatus subscriber task is running
+    pub(crate) alive: bool,
+    /// Whether a DataReader is currently attached
+    pub(crate) reading: bool,
+    pub(crate) restarts: u64,
+    /// Milliseconds since the last SensorStatus sample
+    pub(crate) last_sample_age_ms: Option<u64>,
+}
+
+/// Remote endpoints matched with the gateway's endpoints of one topic. A count is absent when
+/// the gateway has no writer or reader on the topic.
+#[derive(Serialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct TopicHealth {
+    pub(crate) matched_readers: Option<usize>,
+    pub(crate) matched_writers: Option<usize>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct DatabaseHealth {
+    pub(crate) ok: bool,
+    pub(crate) size_on_disk: Option<u64>,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct Health {
+    /// Whether the gateway works or can recover by itself. False means it should be restarted
+    pub(crate) live: bool,
+    /// Whether the gateway can bridge between HTTP and DDS
+    pub(crate) ready: bool,
+    pub(crate) mode: Mode,
+    /// Reasons for not being live or ready
+    pub(crate) problems: Vec<String>,
+    pub(crate) dds: DdsStatus,
+    pub(crate) subscriber: SubscriberHealth,
+    /// Matched endpoints by topic name
+    pub(crate) topics: BTreeMap<String, TopicHealth>,
+    pub(crate) database: DatabaseHealth,
+}
+
+/// Checks the database for the health endpoints. Probes may come every few seconds, so a write
+/// is only done when the last successful one is older than `DATABASE_WRITE_INTERVAL`. A failed
+/// write is retried on the next check.
+#[derive(Clone)]
+pub(crate) struct DatabaseProbe {
+    db: sled::Db,
+    last_write: Arc<Mutex<Option<Instant>>>,
+}
+
+impl DatabaseProbe {
+    pub(crate) fn new(db: sled::Db) -> Self {
+        DatabaseProbe {
+            db,
+            last_write: Arc::default(),
+        }
+    }
+
+    fn check(&self) -> DatabaseHealth {
+        let mut last_write = self.last_write.lock().unwrap_or_else(|e| e.into_inner());
+        let probe = if last_write.is_none_or(|at| at.elapsed() >= DATABASE_WRITE_INTERVAL) {
+            self.db
+                .insert(DATABASE_PROBE_KEY, &history::now_millis().to_be_bytes())
+                .inspect(|_| *last_write = Some(Instant::now()))
+                .map(|_| ())
+        } else {
+            self.db.get(DATABASE_PROBE_KEY).map(|_| ())
+        };
+        drop(last_write);
+        match probe.and_then(|_| self.db.size_on_disk()) {
+            Ok(size) => DatabaseHealth {
+                ok: true,
+                size_on_disk: Some(size),
+                error: None,
+            },
+            Err(e) => DatabaseHealth {
+                ok: false,
+                size_on_disk: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+fn check(state: &AppState) -> Health {
+    let dds = state.dds.status();
+    let connected = dds.state == DdsState::Connected;
+    let ingestion = state.ingestion.read().map(|s| s.clone()).unwrap_or_default();
+    let subscriber = SubscriberHealth {
+        alive: state.dds.subscriber_alive().unwrap_or(false),
+        reading: ingestion.running,
+        restarts: ingestion.restarts,
+        last_sample_age_ms: ingestion
+            .last_sample_at
+            .map(|at| history::now_millis().saturating_sub(at)),
+    };
+
+    let mut topics = BTreeMap::<String, TopicHealth>::new();
+    for (name, entity) in state.qos.entities() {
+        let Some(topic) = entity.topic else {
+            continue;
+        };
+        let matched = state.dds.discovery.matched(&name);
+        let health = topics.entry(topic).or_default();
+        match entity.kind.as_str() {
+            "datawriter" => health.matched_readers = Some(matched),
+            "datareader" => health.matched_writers = Some(matched),
+            _ => {}
+        }
+    }
+    let database = state.database.check();
+
+    let mut live = true;
+    let mut ready = true;
+    let mut problems = vec![];
+    if !database.ok {
+        live = false;
+        problems.push("database fails".to_string());
+    }
+    if !connected {
+        ready = false;
+        problems.push(format!("DDS is not connected after {} attempts", dds.attempts));
+    } else if !subscriber.alive {
+        live = false;
+        problems.push("SensorStatus subscriber task has stopped".to_string());
+    } else if !subscriber.reading {
+        ready = false;
+        problems.push("SensorStatus DataReader is being recreated".to_string());
+    }
+    if let Some(max_age) = state.health.max_sample_age_secs {
+        let stale = subscriber
+            .last_sample_age_ms
+            .is_none_or(|age| age > max_age * 1000);
+        if connected && stale {
+            ready = false;
+            problems.push(format!("no SensorStatus sample within {max_age} s"));
+        }
+    }
+
+    Health {
+        live,
+        ready: live && ready,
+        mode: if connected { Mode::Normal } else { Mode::Degraded },
+        problems,
+        dds,
+        subscriber,
+        topics,
+        database,
+    }
+}
+
+fn respond(ok: bool, health: Health) -> (StatusCode, Json<Health>) {
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(health))
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, body = Health, description = "Gateway is live, possibly in degraded mode while DDS connects"),
+        (status = 503, body = Health, description = "Gateway cannot recover by itself: the database fails or the subscriber task stopped")
+    ),
+    tag = "get_handler_healthz"
+)]
+pub(crate) async fn get_handler_healthz(State(state): State<AppState>) -> (StatusCode, Json<Health>) {
+    let health = check(&state);
+    respond(health.live, health)
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, body = Health, description = "Gateway bridges between HTTP and DDS"),
+        (status = 503, body = Health, description = "Gateway is not ready, `problems` tells why")
+    ),
+    tag = "get_handler_readyz"
+)]
+pub(crate) async fn get_handler_readyz(State(state): State<AppState>) -> (StatusCode, Json<Health>) {
+    let health = check(&state);
+    respond(health.ready, health)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe() -> DatabaseProbe {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        DatabaseProbe::new(db)
+    }
+
+    #[test]
+    fn first_check_writes_the_probe_key() {
+        let probe = probe();
+        let health = probe.check();
+        assert!(health.ok);
+        assert!(health.size_on_disk.is_some());
+        assert!(health.error.is_none());
+        assert!(probe.db.get(DATABASE_PROBE_KEY).unwrap().is_some());
+    }
+
+    #[test]
+    fn checks_within_the_interval_only_read() {
+        let probe = probe();
+        assert!(probe.check().ok);
+        probe.db.remove(DATABASE_PROBE_KEY).unwrap();
+        for _ in 0..3 {
+            assert!(probe.check().ok);
+        }
+        assert!(probe.db.get(DATABASE_PROBE_KEY).unwrap().is_none());
+    }
+
+    #[test]
+    fn writes_again_after_the_interval() {
+        let probe = probe();
+        assert!(probe.check().ok);
+        probe.db.remove(DATABASE_PROBE_KEY).unwrap();
+        *probe.last_write.lock().unwrap() = Instant::now().checked_sub(DATABASE_WRITE_INTERVAL);
+        assert!(probe.check().ok);
+        assert!(probe.db.get(DATABASE_PROBE_KEY).unwrap().is_some());
+    }
+}
@@ -0,0 +1,376 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::retention::{self, Resolution, StatusAggregate};
+use crate::{AppState, SensorStatus};
+
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+/// Value stored in the `history` sled tree, one per received sample.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct HistoryEntry {
+    pub(crate) seq: u64,
+    /// Receive time in milliseconds since the Unix epoch
+    pub(crate) received_at: u64,
+    pub(crate) status: SensorStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct HistoryPage {
+    pub(crate) items: Vec<HistoryEntry>,
+    /// Pass as `cursor` to get the next page. Absent on the last page.
+    pub(crate) next: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub(crate) struct HistoryQuery {
+    /// Start of the range in milliseconds since the Unix epoch (inclusive)
+    from: Option<u64>,
+    /// End of the range in milliseconds since the Unix epoch (exclusive)
+    to: Option<u64>,
+    /// Page size, 100 by default and at most 1000
+    limit: Option<usize>,
+    /// `next` value of the previous page
+    cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct AggregatePage {
+    pub(crate) items: Vec<StatusAggregate>,
+    /// Pass as `cursor` to get the next page. Absent on the last page.
+    pub(crate) next: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub(crate) struct AggregateQuery {
+    /// Bucket size, `minute` by default
+    #[param(inline)]
+    resolution: Option<Resolution>,
+    /// Start of the range in milliseconds since the Unix epoch (inclusive)
+    from: Option<u64>,
+    /// End of the range in milliseconds since the Unix epoch (exclusive)
+    to: Option<u64>,
+    /// Page size, 100 by default and at most 1000
+    limit: Option<usize>,
+    /// `next` value of the previous page
+    cursor: Option<u64>,
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// `sensor_type`, a NUL separator, then big-endian receive time and sequence number,
+/// so that the entries of one sensor are contiguous and ordered by time.
+pub(crate) fn history_key(sensor_type: &str, received_at: u64, seq: u64) -> Vec<u8> {
+    let mut key = history_prefix(sensor_type);
+    key.extend_from_slice(&received_at.to_be_bytes());
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+pub(crate) fn history_prefix(sensor_type: &str) -> Vec<u8> {
+    let mut prefix = sensor_type.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+fn encode_cursor(received_at: u64, seq: u64) -> String {
+    format!("{received_at}-{seq}")
+}
+
+fn decode_cursor(cursor: &str) -> Option<(u64, u64)> {
+    let (received_at, seq) = cursor.split_once('-')?;
+    Some((received_at.parse().ok()?, seq.parse().ok()?))
+}
+
+/// Receive time of the last sample of a sensor in milliseconds since the Unix epoch.
+pub(crate) fn last_received_at(db_history: &sled::Tree, sensor_type: &str) -> sled::Result<Option<u64>> {
+    let prefix = history_prefix(sensor_type);
+    let Some((key, _)) = db_history.scan_prefix(&prefix).next_back().transpose()? else {
+        return Ok(None);
+    };
+    Ok(key[prefix.len()..]
+        .get(..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_be_bytes))
+}
+
+pub(crate) fn append_history(
+    db_history: &sled::Tree,
+    received_at: u64,
+    seq: u64,
+    status: &SensorStatus,
+) -> sled::Result<()> {
+    let entry = HistoryEntry {
+        seq,
+        received_at,
+        status: status.clone(),
+    };
+    let json = serde_json::to_vec(&entry).expect("HistoryEntry always serializes");
+    db_history.insert(history_key(&status.sensor_type, received_at, seq), json)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/status/{sensor_type}/history",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)"),
+        HistoryQuery
+    ),
+    responses(
+        (status = 200, body = HistoryPage, description = "Get received statuses of a sensor, oldest first"),
+        (status = 400, body = Problem, content_type = "application/problem+json", description = "Invalid range or cursor"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_sensor_status_history"
+)]
+pub(crate) async fn get_handler_sensor_status_history(
+    State(state): State<AppState>,
+    Path(sensor_type): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<(StatusCode, Json<HistoryPage>), ApiError> {
+    let page = history_page(&state.history, &sensor_type, &query)?;
+    Ok((StatusCode::OK, Json(page)))
+}
+
+/// One page of the history of a sensor, read from the `history` tree.
+fn history_page(db_history: &sled::Tree, sensor_type: &str, query: &HistoryQuery) -> Result<HistoryPage, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or(u64::MAX);
+    if from >= to {
+        return Err(ApiError::bad_request(format!("from ({from}) must be before to ({to})")));
+    }
+
+    // continue right after the last entry of the previous page
+    let start = match query.cursor.as_deref().map(decode_cursor) {
+        Some(Some((received_at, seq))) => {
+            let mut key = history_key(sensor_type, received_at, seq);
+            key.push(0);
+            key
+        }
+        Some(None) => return Err(ApiError::bad_request("invalid cursor")),
+        None => vec![],
+    };
+    let start = start.max(history_key(sensor_type, from, 0));
+    let end = history_key(sensor_type, to, 0);
+    if start >= end {
+        return Ok(HistoryPage::default());
+    }
+
+    let mut page = HistoryPage::default();
+    for item in db_history.range(start..end) {
+        let (_, value) = item?;
+        if page.items.len() == limit {
+            let last = page.items.last().expect("limit is at least 1");
+            page.next = Some(encode_cursor(last.received_at, last.seq));
+            break;
+        }
+        if let Ok(entry) = serde_json::from_slice::<HistoryEntry>(&value) {
+            page.items.push(entry);
+        }
+    }
+    Ok(page)
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/status/{sensor_type}/aggregates",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)"),
+        AggregateQuery
+    ),
+    responses(
+        (status = 200, body = AggregatePage, description = "Get per-minute or per-hour aggregates of downsampled statuses, oldest first"),
+        (status = 400, body = Problem, content_type = "application/problem+json", description = "Invalid range"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_sensor_status_aggregates"
+)]
+pub(crate) async fn get_handler_sensor_status_aggregates(
+    State(state): State<AppState>,
+    Path(sensor_type): Path<String>,
+    Query(query): Query<AggregateQuery>,
+) -> Result<(StatusCode, Json<AggregatePage>), ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or(u64::MAX);
+    if from >= to {
+        return Err(ApiError::bad_request(format!("from ({from}) must be before to ({to})")));
+    }
+    let tree = match query.resolution.unwrap_or_default() {
+        Resolution::Minute => &state.aggregates_minute,
+        Resolution::Hour => &state.aggregates_hour,
+    };
+
+    // continue right after the last bucket of the previous page
+    let start = match query.cursor {
+        Some(cursor) => {
+            let mut key = retention::aggregate_key(&sensor_type, cursor);
+            key.push(0);
+            key
+        }
+        None => vec![],
+    };
+    let start = start.max(retention::aggregate_key(&sensor_type, from));
+    let end = retention::aggregate_key(&sensor_type, to);
+    if start >= end {
+        return Ok((StatusCode::OK, Json(AggregatePage::default())));
+    }
+
+    let mut page = AggregatePage::default();
+    for item in tree.range(start..end) {
+        let (_, value) = item?;
+        if page.items.len() == limit {
+            let last = page.items.last().expect("limit is at least 1");
+            page.next = Some(last.bucket_start.to_string());
+            break;
+        }
+        if let Ok(aggregate) = serde_json::from_slice::<StatusAggregate>(&value) {
+            page.items.push(aggregate);
+        }
+    }
+    Ok((StatusCode::OK, Json(page)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    fn history() -> sled::Tree {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.open_tree("history").unwrap()
+    }
+
+    fn insert(db_history: &sled::Tree, sensor_type: &str, received_at: u64, seq: u64) {
+        let status = SensorStatus {
+            sensor_type: sensor_type.to_string(),
+            ..SensorStatus::default()
+        };
+        append_history(db_history, received_at, seq, &status).unwrap();
+    }
+
+    fn page(db_history: &sled::Tree, query: HistoryQuery) -> HistoryPage {
+        history_page(db_history, "radar", &query).unwrap()
+    }
+
+    fn times(page: &HistoryPage) -> Vec<u64> {
+        page.items.iter().map(|entry| entry.received_at).collect()
+    }
+
+    #[test]
+    fn cursor_continues_after_the_previous_page() {
+        let db_history = history();
+        for (seq, received_at) in [1000, 1000, 2000, 3000, 4000].into_iter().enumerate() {
+            insert(&db_history, "radar", received_at, seq as u64);
+        }
+        insert(&db_history, "sonar", 1500, 5);
+
+        let mut seqs = vec![];
+        let mut cursor = None;
+        loop {
+            let page = page(&db_history, HistoryQuery { limit: Some(2), cursor, ..HistoryQuery::default() });
+            seqs.extend(page.items.iter().map(|entry| entry.seq));
+            assert!(page.items.iter().all(|entry| entry.status.sensor_type == "radar"));
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seqs, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn full_last_page_has_no_cursor() {
+        let db_history = history();
+        for seq in 0..4 {
+            insert(&db_history, "radar", 1000 + seq, seq);
+        }
+        let first = page(&db_history, HistoryQuery { limit: Some(2), ..HistoryQuery::default() });
+        assert_eq!(times(&first), [1000, 1001]);
+        assert_eq!(first.next.as_deref(), Some("1001-1"));
+
+        let last = page(&db_history, HistoryQuery { limit: Some(2), cursor: first.next, ..HistoryQuery::default() });
+        assert_eq!(times(&last), [1002, 1003]);
+        assert!(last.next.is_none());
+    }
+
+    #[test]
+    fn from_is_inclusive_and_to_exclusive() {
+        let db_history = history();
+        for seq in 0..5 {
+            insert(&db_history, "radar", 1000 * (seq + 1), seq);
+        }
+        let page = page(&db_history, HistoryQuery { from: Some(2000), to: Some(4000), ..HistoryQuery::default() });
+        assert_eq!(times(&page), [2000, 3000]);
+        assert!(page.next.is_none());
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let db_history = history();
+        for seq in 0..MAX_HISTORY_LIMIT as u64 + 1 {
+            insert(&db_history, "radar", 1000 + seq, seq);
+        }
+        let smallest = page(&db_history, HistoryQuery { limit: Some(0), ..HistoryQuery::default() });
+        assert_eq!(times(&smallest), [1000]);
+        assert!(smallest.next.is_some());
+
+        let default = page(&db_history, HistoryQuery::default());
+        assert_eq!(default.items.len(), DEFAULT_HISTORY_LIMIT);
+
+        let largest = page(&db_history, HistoryQuery { limit: Some(usize::MAX), ..HistoryQuery::default() });
+        assert_eq!(largest.items.len(), MAX_HISTORY_LIMIT);
+        assert!(largest.next.is_some());
+    }
+
+    #[test]
+    fn empty_ranges_return_an_empty_page() {
+        let db_history = history();
+        insert(&db_history, "radar", 1000, 0);
+        insert(&db_history, "radar", 5000, 1);
+
+        let between = page(&db_history, HistoryQuery { from: Some(2000), to: Some(3000), ..HistoryQuery::default() });
+        assert!(between.items.is_empty());
+        assert!(between.next.is_none());
+
+        // a cursor past `to` leaves nothing to read
+        let past = page(
+            &db_history,
+            HistoryQuery { to: Some(3000), cursor: Some(encode_cursor(5000, 1)), ..HistoryQuery::default() },
+        );
+        assert!(past.items.is_empty());
+
+        let unknown = history_page(&db_history, "sonar", &HistoryQuery::default()).unwrap();
+        assert!(unknown.items.is_empty());
+    }
+
+    #[test]
+    fn rejects_inverted_ranges_and_invalid_cursors() {
+        let db_history = history();
+        for query in [
+            HistoryQuery { from: Some(2000), to: Some(2000), ..HistoryQuery::default() },
+            HistoryQuery { cursor: Some("1000".to_string()), ..HistoryQuery::default() },
+        ] {
+            let problem = history_page(&db_history, "radar", &query).unwrap_err().problem();
+            assert_eq!(problem.code, ErrorCode::BadRequest);
+        }
+    }
+}
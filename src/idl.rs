@@ -0,0 +1,900 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::layout::{FieldLayout, FieldType, TypeDefs};
+
+/// Nesting limit for typedefs referring to typedefs.
+const MAX_TYPEDEF_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Text(String),
+    /// `::`
+    Scope,
+    Punct(char),
+}
+
+#[derive(Debug, Clone)]
+enum RawType {
+    Primitive(FieldType),
+    Sequence(Box<RawType>, Option<u32>),
+    Array(Box<RawType>, u32),
+    /// Scoped name as written, resolved from the scope of its definition
+    Named(String),
+}
+
+#[derive(Debug)]
+struct RawMember {
+    name: String,
+    raw_type: RawType,
+    key: bool,
+}
+
+#[derive(Debug)]
+struct RawStruct {
+    scope: Vec<String>,
+    base: Option<String>,
+    members: Vec<RawMember>,
+    location: String,
+}
+
+#[derive(Default)]
+struct Definitions {
+    structs: BTreeMap<String, RawStruct>,
+    enums: BTreeMap<String, Vec<String>>,
+    typedefs: BTreeMap<String, (Vec<String>, RawType)>,
+    consts: BTreeMap<String, u64>,
+    /// `#pragma keylist Type field...`, as written
+    keylists: Vec<(String, Vec<String>, String)>,
+}
+
+impl Definitions {
+    fn is_defined(&self, name: &str) -> bool {
+        self.structs.contains_key(name)
+            || self.enums.contains_key(name)
+            || self.typedefs.contains_key(name)
+            || self.consts.contains_key(name)
+    }
+
+    /// Resolves a scoped name the way IDL does: from the innermost enclosing module outwards.
+    fn lookup(&self, name: &str, scope: &[String]) -> Option<String> {
+        if let Some(absolute) = name.strip_prefix("::") {
+            return self.is_defined(absolute).then(|| absolute.to_string());
+        }
+        (0..=scope.len()).rev().find_map(|depth| {
+            let candidate = if depth == 0 {
+                name.to_string()
+            } else {
+                format!("{}::{name}", scope[..depth].join("::"))
+            };
+            self.is_defined(&candidate).then_some(candidate)
+        })
+    }
+
+    fn resolve(&self, raw_type: &RawType, scope: &[String], depth: usize) -> Result<FieldType, String> {
+        if depth > MAX_TYPEDEF_DEPTH {
+            return Err(String::from("typedefs nest too deep"));
+        }
+        Ok(match raw_type {
+            RawType::Primitive(field_type) => field_type.clone(),
+            RawType::Sequence(element, bound) => FieldType::Sequence {
+                element: Box::new(self.resolve(element, scope, depth)?),
+                bound: *bound,
+            },
+            RawType::Array(element, length) => FieldType::Array {
+                element: Box::new(self.resolve(element, scope, depth)?),
+                length: *length,
+            },
+            RawType::Named(name) => {
+                let Some(resolved) = self.lookup(name, scope) else {
+                    return Err(format!("unknown type {name}"));
+                };
+                if self.structs.contains_key(&resolved) {
+                    FieldType::Struct { name: resolved }
+                } else if self.enums.contains_key(&resolved) {
+                    FieldType::Enum { name: resolved }
+                } else if let Some((typedef_scope, aliased)) = self.typedefs.get(&resolved) {
+                    self.resolve(aliased, typedef_scope, depth + 1)?
+                } else {
+                    return Err(format!("{name} is not a type"));
+                }
+            }
+        })
+    }
+
+    /// Members of a struct, those of its base struct first.
+    fn members(&self, name: &str, depth: usize) -> Result<Vec<FieldLayout>> {
+        let raw = &self.structs[name];
+        let mut fields = match &raw.base {
+            Some(base) if depth > MAX_TYPEDEF_DEPTH => {
+                bail!("{}: base struct {base} nests too deep", raw.location)
+            }
+            Some(base) => {
+                let resolved = self
+                    .lookup(base, &raw.scope)
+                    .filter(|resolved| self.structs.contains_key(resolved))
+                    .ok_or_else(|| anyhow!("{}: unknown base struct {base}", raw.location))?;
+                self.members(&resolved, depth + 1)?
+            }
+            None => vec![],
+        };
+        for member in &raw.members {
+            let field_type = self
+                .resolve(&member.raw_type, &raw.scope, 0)
+                .map_err(|e| anyhow!("{}: member {}: {e}", raw.location, member.name))?;
+            if fields.iter().any(|field| field.name == member.name) {
+                bail!("{}: member {} is declared twice", raw.location, member.name);
+            }
+            fields.push(FieldLayout {
+                name: member.name.clone(),
+                field_type,
+                key: member.key,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn into_type_defs(self) -> Result<TypeDefs> {
+        let mut types = TypeDefs {
+            enums: self.enums.clone(),
+            ..TypeDefs::default()
+        };
+        for name in self.structs.keys() {
+            types.structs.insert(name.clone(), self.members(name, 0)?);
+        }
+        for (type_name, keys, location) in &self.keylists {
+            let name = type_name.trim_start_matches("::");
+            let candidates = types
+                .structs
+                .keys()
+                .filter(|candidate| *candidate == name || candidate.ends_with(&format!("::{name}")))
+                .cloned()
+                .collect::<Vec<_>>();
+            let [resolved] = candidates.as_slice() else {
+                bail!("{location}: #pragma keylist refers to unknown or ambiguous struct {type_name}");
+            };
+            let fields = types.structs.get_mut(resolved).expect("listed above");
+            for key in keys {
+                let field = fields
+                    .iter_mut()
+                    .find(|field| &field.name == key)
+                    .ok_or_else(|| anyhow!("{location}: #pragma keylist: {resolved} has no member {key}"))?;
+                field.key = true;
+            }
+        }
+        Ok(types)
+    }
+}
+
+/// Parses IDL files into the structs and enums they declare.
+pub(crate) fn load(paths: &[PathBuf]) -> Result<TypeDefs> {
+    let mut definitions = Definitions::default();
+    for path in paths {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read IDL file {}", path.display()))?;
+        parse(path, &text, &mut definitions)?;
+    }
+    definitions.into_type_defs()
+}
+
+/// Parses IDL text that is not read from a file, such as an IDL file embedded in the binary.
+pub(crate) fn parse_text(path: &Path, text: &str) -> Result<TypeDefs> {
+    let mut definitions = Definitions::default();
+    parse(path, text, &mut definitions)?;
+    definitions.into_type_defs()
+}
+
+fn parse(path: &Path, text: &str, definitions: &mut Definitions) -> Result<()> {
+    let source = strip_comments(text);
+    let mut code = String::with_capacity(source.len());
+    for (index, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(directive) = trimmed.strip_prefix('#') {
+            // includes are expected to be listed in idl_files as well
+            let mut words = directive.split_whitespace();
+            if words.next() == Some("pragma") && words.next() == Some("keylist") {
+                let location = format!("{}:{}", path.display(), index + 1);
+                let type_name = words
+                    .next()
+                    .ok_or_else(|| anyhow!("{location}: #pragma keylist without a type"))?;
+                definitions.keylists.push((
+                    type_name.to_string(),
+                    words.map(str::to_string).collect(),
+                    location,
+                ));
+            }
+        } else {
+            code.push_str(line);
+        }
+        code.push('\n');
+    }
+    let mut parser = Parser {
+        path,
+        tokens: tokenize(path, &code)?,
+        pos: 0,
+        scope: vec![],
+        definitions,
+    };
+    parser.definitions_until_end()
+}
+
+/// Replaces comments with whitespace, keeping line numbers.
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_text = false;
+    while let Some(c) = chars.next() {
+        if in_text {
+            in_text = c != '"';
+            out.push(c);
+        } else if c == '"' {
+            in_text = true;
+            out.push(c);
+        } else if c == '/' && chars.peek() == Some(&'/') {
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    out.push('\n');
+                    break;
+                }
+            }
+        } else if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut previous = ' ';
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    out.push('\n');
+                }
+                if previous == '*' && c == '/' {
+                    break;
+                }
+                previous = c;
+            }
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn tokenize(path: &Path, code: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = vec![];
+    let chars = code.chars().collect::<Vec<_>>();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                i += 1;
+            }
+            let literal = chars[start..i].iter().collect::<String>();
+            let number = match literal.strip_prefix("0x").or_else(|| literal.strip_prefix("0X")) {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => literal.parse::<u64>().ok(),
+            };
+            // floating point literals only appear in constants and annotations
+            tokens.push((number.map_or(Token::Text(literal), Token::Number), line));
+        } else if c == '"' || c == '\'' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            if i == chars.len() {
+                bail!("{}:{line}: unterminated literal", path.display());
+            }
+            tokens.push((Token::Text(chars[start..i].iter().collect()), line));
+            i += 1;
+        } else if c == ':' && chars.get(i + 1) == Some(&':') {
+            tokens.push((Token::Scope, line));
+            i += 2;
+        } else {
+            tokens.push((Token::Punct(c), line));
+            i += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+struct Annotation {
+    name: String,
+    args: Vec<Token>,
+}
+
+struct Parser<'a> {
+    path: &'a Path,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    scope: Vec<String>,
+    definitions: &'a mut Definitions,
+}
+
+impl Parser<'_> {
+    fn location(&self) -> String {
+        let line = self
+            .tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line);
+        format!("{}:{line}", self.path.display())
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow!("{}: unexpected end of file", self.location()))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident == keyword)
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<()> {
+        if self.is_punct(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            bail!("{}: expected '{c}', found {}", self.location(), self.describe())
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => bail!("{}: expected a name, found {}", self.location(), self.describe()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Some(Token::Ident(ident)) => format!("\"{ident}\""),
+            Some(Token::Number(number)) => number.to_string(),
+            Some(Token::Text(text)) => format!("\"{text}\""),
+            Some(Token::Scope) => String::from("'::'"),
+            Some(Token::Punct(c)) => format!("'{c}'"),
+            None => String::from("end of file"),
+        }
+    }
+
+    fn scoped_name(&mut self) -> Result<String> {
+        let mut name = String::new();
+        if self.peek() == Some(&Token::Scope) {
+            self.pos += 1;
+            name.push_str("::");
+        }
+        name.push_str(&self.ident()?);
+        while self.peek() == Some(&Token::Scope) {
+            self.pos += 1;
+            name.push_str("::");
+            name.push_str(&self.ident()?);
+        }
+        Ok(name)
+    }
+
+    fn qualified(&self, name: &str) -> String {
+        self.scope
+            .iter()
+            .map(String::as_str)
+            .chain([name])
+            .collect::<Vec<_>>()
+            .join("::")
+    }
+
+    fn annotations(&mut self) -> Result<Vec<Annotation>> {
+        let mut annotations = vec![];
+        while self.is_punct('@') {
+            self.pos += 1;
+            let name = self.scoped_name()?;
+            let mut args = vec![];
+            if self.is_punct('(') {
+                self.pos += 1;
+                let mut depth = 1;
+                loop {
+                    let token = self.next()?;
+                    match token {
+                        Token::Punct('(') => depth += 1,
+                        Token::Punct(')') => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    args.push(token);
+                }
+            }
+            annotations.push(Annotation { name, args });
+        }
+        Ok(annotations)
+    }
+
+    fn definitions_until_end(&mut self) -> Result<()> {
+        while self.peek().is_some() {
+            self.definition()?;
+        }
+        Ok(())
+    }
+
+    fn definition(&mut self) -> Result<()> {
+        let annotations = self.annotations()?;
+        let location = self.location();
+        let keyword = self.ident()?;
+        match keyword.as_str() {
+            "module" => {
+                let name = self.ident()?;
+                self.expect_punct('{')?;
+                self.scope.push(name);
+                while !self.is_punct('}') {
+                    self.definition()?;
+                }
+                self.pos += 1;
+                self.scope.pop();
+            }
+            "struct" => self.struct_definition(&annotations)?,
+            "enum" => self.enum_definition()?,
+            "typedef" => {
+                let raw_type = self.type_spec()?;
+                loop {
+                    let (name, raw_type) = self.declarator(raw_type.clone())?;
+                    let qualified = self.qualified(&name);
+                    self.define(&qualified)?;
+                    self.definitions
+                        .typedefs
+                        .insert(qualified, (self.scope.clone(), raw_type));
+                    if !self.is_punct(',') {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            }
+            "const" => {
+                // only integer constants matter, as bounds of strings, sequences and arrays
+                self.type_spec()?;
+                let name = self.ident()?;
+                let name = self.qualified(&name);
+                self.expect_punct('=')?;
+                let value = self.next()?;
+                while !self.is_punct(';') {
+                    self.next()?;
+                }
+                self.define(&name)?;
+                if let Token::Number(value) = value {
+                    self.definitions.consts.insert(name, value);
+                }
+            }
+            other => bail!("{location}: \"{other}\" definitions are not supported"),
+        }
+        self.expect_punct(';')
+    }
+
+    fn define(&self, qualified: &str) -> Result<()> {
+        if self.definitions.is_defined(qualified) {
+            bail!("{}: {qualified} is defined twice", self.location());
+        }
+        Ok(())
+    }
+
+    fn struct_definition(&mut self, annotations: &[Annotation]) -> Result<()> {
+        let location = self.location();
+        let name = self.ident()?;
+        let qualified = self.qualified(&name);
+        // forward declaration
+        if self.is_punct(';') {
+            return Ok(());
+        }
+        for annotation in annotations {
+            let mutable = match annotation.name.as_str() {
+                "mutable" => true,
+                "extensibility" => annotation.args.first() == Some(&Token::Ident(String::from("MUTABLE"))),
+                _ => false,
+            };
+            if mutable {
+                bail!("{location}: struct {qualified}: mutable extensibility is not supported");
+            }
+        }
+        let base = if self.is_punct(':') {
+            self.pos += 1;
+            Some(self.scoped_name()?)
+        } else {
+            None
+        };
+        self.expect_punct('{')?;
+        let mut members = vec![];
+        while !self.is_punct('}') {
+            let annotations = self.annotations()?;
+            let mut key = false;
+            for annotation in &annotations {
+                match annotation.name.as_str() {
+                    "key" => {
+                        key = !matches!(
+                            annotation.args.first(),
+                            Some(Token::Ident(value)) if value.eq_ignore_ascii_case("false")
+                        )
+                    }
+                    "optional" | "external" | "shared" => bail!(
+                        "{}: @{} members are not supported",
+                        self.location(),
+                        annotation.name
+                    ),
+                    _ => {}
+                }
+            }
+            let raw_type = self.type_spec()?;
+            loop {
+                let (name, raw_type) = self.declarator(raw_type.clone())?;
+                members.push(RawMember { name, raw_type, key });
+                if !self.is_punct(',') {
+                    break;
+                }
+                self.pos += 1;
+            }
+            self.expect_punct(';')?;
+        }
+        self.pos += 1;
+        if members.is_empty() && base.is_none() {
+            bail!("{location}: struct {qualified} has no members");
+        }
+        self.define(&qualified)?;
+        self.definitions.structs.insert(
+            qualified,
+            RawStruct {
+                scope: self.scope.clone(),
+                base,
+                members,
+                location,
+            },
+        );
+        Ok(())
+    }
+
+    fn enum_definition(&mut self) -> Result<()> {
+        let name = self.ident()?;
+        let qualified = self.qualified(&name);
+        self.expect_punct('{')?;
+        let mut variants = vec![];
+        while !self.is_punct('}') {
+            for annotation in self.annotations()? {
+                if annotation.name == "value" {
+                    bail!("{}: @value on enumerators is not supported", self.location());
+                }
+            }
+            variants.push(self.ident()?);
+            if !self.is_punct(',') {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.expect_punct('}')?;
+        if variants.is_empty() {
+            bail!("{}: enum {qualified} has no enumerators", self.location());
+        }
+        self.define(&qualified)?;
+        // enumerators are constants of the enclosing scope, they only need to be unique
+        self.definitions.enums.insert(qualified, variants);
+        Ok(())
+    }
+
+    /// `name` followed by any number of `[length]`.
+    fn declarator(&mut self, raw_type: RawType) -> Result<(String, RawType)> {
+        let name = self.ident()?;
+        let mut lengths = vec![];
+        while self.is_punct('[') {
+            self.pos += 1;
+            lengths.push(self.bound()?);
+            self.expect_punct(']')?;
+        }
+        // `long m[2][3]` is an array of 2 arrays of 3
+        let raw_type = lengths
+            .into_iter()
+            .rev()
+            .fold(raw_type, |element, length| RawType::Array(Box::new(element), length));
+        Ok((name, raw_type))
+    }
+
+    fn bound(&mut self) -> Result<u32> {
+        let location = self.location();
+        let value = match self.peek() {
+            Some(Token::Number(number)) => {
+                let number = *number;
+                self.pos += 1;
+                number
+            }
+            Some(Token::Ident(_) | Token::Scope) => {
+                let name = self.scoped_name()?;
+                self.definitions
+                    .lookup(&name, &self.scope)
+                    .and_then(|resolved| self.definitions.consts.get(&resolved).copied())
+                    .ok_or_else(|| anyhow!("{location}: {name} is not an integer constant"))?
+            }
+            _ => bail!("{location}: expected a bound, found {}", self.describe()),
+        };
+        u32::try_from(value)
+            .ok()
+            .filter(|value| *value > 0)
+            .ok_or_else(|| anyhow!("{location}: bound {value} is out of range"))
+    }
+
+    fn type_spec(&mut self) -> Result<RawType> {
+        let location = self.location();
+        if self.peek() == Some(&Token::Scope) {
+            return Ok(RawType::Named(self.scoped_name()?));
+        }
+        let keyword = self.ident()?;
+        let primitive = match keyword.as_str() {
+            "boolean" => FieldType::Bool,
+            "octet" | "uint8" => FieldType::U8,
+            "int8" => FieldType::I8,
+            "short" | "int16" => FieldType::I16,
+            "uint16" => FieldType::U16,
+            "int32" => FieldType::I32,
+            "uint32" => FieldType::U32,
+            "int64" => FieldType::I64,
+            "uint64" => FieldType::U64,
+            "float" => FieldType::F32,
+            "double" => FieldType::F64,
+            "long" => {
+                if self.is_keyword("long") {
+                    self.pos += 1;
+                    FieldType::I64
+                } else if self.is_keyword("double") {
+                    bail!("{location}: long double is not supported")
+                } else {
+                    FieldType::I32
+                }
+            }
+            "unsigned" => match self.ident()?.as_str() {
+                "short" => FieldType::U16,
+                "long" if self.is_keyword("long") => {
+                    self.pos += 1;
+                    FieldType::U64
+                }
+                "long" => FieldType::U32,
+                other => bail!("{location}: unsigned {other} is not a valid type"),
+            },
+            "string" => {
+                // the bound of a string is not checked
+                if self.is_punct('<') {
+                    self.pos += 1;
+                    self.bound()?;
+                    self.expect_punct('>')?;
+                }
+                FieldType::String
+            }
+            "sequence" => {
+                self.expect_punct('<')?;
+                let element = self.type_spec()?;
+                let bound = if self.is_punct(',') {
+                    self.pos += 1;
+                    Some(self.bound()?)
+                } else {
+                    None
+                };
+                self.expect_punct('>')?;
+                return Ok(RawType::Sequence(Box::new(element), bound));
+            }
+            "char" | "wchar" | "wstring" | "any" | "fixed" | "map" | "Object" | "ValueBase" => {
+                bail!("{location}: {keyword} is not supported")
+            }
+            _ => {
+                // scoped name starting with an identifier
+                let mut name = keyword;
+                while self.peek() == Some(&Token::Scope) {
+                    self.pos += 1;
+                    name.push_str("::");
+                    name.push_str(&self.ident()?);
+                }
+                return Ok(RawType::Named(name));
+            }
+        };
+        Ok(RawType::Primitive(primitive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_idl(text: &str) -> Result<TypeDefs> {
+        parse_text(Path::new("test.idl"), text)
+    }
+
+    fn error(text: &str) -> String {
+        parse_idl(text).unwrap_err().to_string()
+    }
+
+    fn field(name: &str, field_type: FieldType, key: bool) -> FieldLayout {
+        FieldLayout {
+            name: name.to_string(),
+            field_type,
+            key,
+        }
+    }
+
+    #[test]
+    fn parses_primitives_and_key() {
+        let types = parse_idl(
+            "module sensors {
+                struct Reading {
+                    @key string sensor_type;
+                    @key(FALSE) unsigned long long sequence;
+                    long celsius, kelvin;
+                    unsigned short channel;
+                    octet flags;
+                    boolean valid;
+                    double gain;
+                };
+            };",
+        )
+        .unwrap();
+        assert_eq!(
+            types.structs["sensors::Reading"],
+            vec![
+                field("sensor_type", FieldType::String, true),
+                field("sequence", FieldType::U64, false),
+                field("celsius", FieldType::I32, false),
+                field("kelvin", FieldType::I32, false),
+                field("channel", FieldType::U16, false),
+                field("flags", FieldType::U8, false),
+                field("valid", FieldType::Bool, false),
+                field("gain", FieldType::F64, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_enums_and_nested_structs() {
+        let types = parse_idl(
+            "module engine {
+                enum Mode { IDLE, RUNNING, @default_literal FAILED };
+                struct Position { double lat; double lon; };
+                module status {
+                    struct Engine {
+                        @key uint32 id;
+                        Mode mode;
+                        ::engine::Position position;
+                    };
+                };
+            };",
+        )
+        .unwrap();
+        assert_eq!(types.enums["engine::Mode"], vec!["IDLE", "RUNNING", "FAILED"]);
+        assert_eq!(
+            types.structs["engine::status::Engine"],
+            vec![
+                field("id", FieldType::U32, true),
+                field("mode", FieldType::Enum { name: "engine::Mode".to_string() }, false),
+                field(
+                    "position",
+                    FieldType::Struct { name: "engine::Position".to_string() },
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_sequences_arrays_and_typedefs() {
+        let types = parse_idl(
+            "const long MAX_SAMPLES = 16;
+            typedef sequence<float, MAX_SAMPLES> Samples;
+            struct Trace {
+                @key string<32> name;
+                Samples samples;
+                sequence<sequence<octet> > blobs;
+                short matrix[2][3];
+            };",
+        )
+        .unwrap();
+        let fields = &types.structs["Trace"];
+        assert_eq!(fields[0], field("name", FieldType::String, true));
+        assert_eq!(
+            fields[1].field_type,
+            FieldType::Sequence {
+                element: Box::new(FieldType::F32),
+                bound: Some(16)
+            }
+        );
+        assert_eq!(
+            fields[2].field_type,
+            FieldType::Sequence {
+                element: Box::new(FieldType::Sequence {
+                    element: Box::new(FieldType::U8),
+                    bound: None
+                }),
+                bound: None
+            }
+        );
+        assert_eq!(
+            fields[3].field_type,
+            FieldType::Array {
+                element: Box::new(FieldType::Array {
+                    element: Box::new(FieldType::I16),
+                    length: 3
+                }),
+                length: 2
+            }
+        );
+    }
+
+    #[test]
+    fn base_members_come_first_and_keylist_sets_keys() {
+        let types = parse_idl(
+            "/* header */
+            module m {
+                struct Base { string site; };
+                struct Derived : Base { long value; }; // trailing
+            };
+            #pragma keylist Derived site
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            types.structs["m::Derived"],
+            vec![
+                field("site", FieldType::String, true),
+                field("value", FieldType::I32, false),
+            ]
+        );
+        assert!(!types.structs["m::Base"][0].key);
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        let message = error("struct A {\n  Missing value;\n};");
+        assert!(message.contains("test.idl:1"), "{message}");
+        assert!(message.contains("unknown type Missing"), "{message}");
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        assert!(error("struct A { long a; long a; };").contains("declared twice"));
+        assert!(error("struct A { long a; };\nstruct A { long b; };").contains("defined twice"));
+        assert!(error("struct A { };").contains("has no members"));
+        assert!(error("enum E { };").contains("has no enumerators"));
+        assert!(error("struct A { char c; };").contains("char is not supported"));
+        assert!(error("struct A { @optional long a; };").contains("@optional members are not supported"));
+        assert!(error("@mutable struct A { long a; };").contains("mutable extensibility"));
+        assert!(error("struct A { sequence<long, 0> a; };").contains("out of range"));
+        assert!(error("union U switch (long) { case 1: long a; };").contains("\"union\" definitions are not supported"));
+        assert!(error("struct A { long a; }").contains("expected ';', found end of file"));
+        assert!(error("struct A { string a = \"x; };").contains("unterminated literal"));
+        assert!(error("struct A { long a; };\n#pragma keylist B a").contains("unknown or ambiguous struct B"));
+        assert!(error("struct A { long a; };\n#pragma keylist A b").contains("A has no member b"));
+    }
+
+    #[test]
+    fn rejects_typedef_cycles() {
+        let message = error("typedef B A;\ntypedef A B;\nstruct S { A a; };");
+        assert!(message.contains("nest too deep"), "{message}");
+    }
+}
@@ -0,0 +1,1011 @@
+use cdr_encoding_size::CdrEncodingMaxSize;
+use serde::de::{self, DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Error as _, SerializeSeq, SerializeTuple, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use utoipa::openapi::schema::{
+    ArrayBuilder, KnownFormat, ObjectBuilder, Schema, SchemaFormat, SchemaType,
+};
+use utoipa::openapi::{OpenApi, Ref, RefOr};
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FieldType {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    String,
+    /// Variable length sequence, optionally bounded
+    Sequence {
+        element: Box<FieldType>,
+        bound: Option<u32>,
+    },
+    /// Fixed length array
+    Array { element: Box<FieldType>, length: u32 },
+    /// Enum declared in IDL, by fully qualified name
+    Enum { name: String },
+    /// Struct declared in IDL, by fully qualified name
+    Struct { name: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FieldLayout {
+    pub(crate) name: String,
+    #[serde(rename = "type")]
+    pub(crate) field_type: FieldType,
+    #[serde(default)]
+    pub(crate) key: bool,
+}
+
+/// Structs and enums declared in IDL, by fully qualified name such as `sensors::Mode`.
+#[derive(Serialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct TypeDefs {
+    pub(crate) structs: BTreeMap<String, Vec<FieldLayout>>,
+    pub(crate) enums: BTreeMap<String, Vec<String>>,
+}
+
+impl TypeDefs {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.structs.is_empty() && self.enums.is_empty()
+    }
+
+    /// First enum or struct used by the fields that is not defined.
+    pub(crate) fn unknown_type<'a>(&self, fields: &'a [FieldLayout]) -> Option<&'a str> {
+        fn find<'a>(types: &TypeDefs, field_type: &'a FieldType) -> Option<&'a str> {
+            match field_type {
+                FieldType::Sequence { element, .. } | FieldType::Array { element, .. } => {
+                    find(types, element)
+                }
+                FieldType::Enum { name } => (!types.enums.contains_key(name)).then_some(name),
+                FieldType::Struct { name } => (!types.structs.contains_key(name)).then_some(name),
+                _ => None,
+            }
+        }
+        fields.iter().find_map(|field| find(self, &field.field_type))
+    }
+
+    /// Only the definitions reachable from the given fields.
+    pub(crate) fn referenced_by(&self, fields: &[FieldLayout]) -> TypeDefs {
+        fn visit(all: &TypeDefs, ty: &FieldType, used: &mut TypeDefs) {
+            match ty {
+                FieldType::Sequence { element, .. } | FieldType::Array { element, .. } => {
+                    visit(all, element, used)
+                }
+                FieldType::Enum { name } => {
+                    if let Some(variants) = all.enums.get(name) {
+                        used.enums.insert(name.clone(), variants.clone());
+                    }
+                }
+                FieldType::Struct { name } => {
+                    if used.structs.contains_key(name) {
+                        return;
+                    }
+                    if let Some(fields) = all.structs.get(name) {
+                        used.structs.insert(name.clone(), fields.clone());
+                        for field in fields {
+                            visit(all, &field.field_type, used);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut used = TypeDefs::default();
+        for field in fields {
+            visit(self, &field.field_type, &mut used);
+        }
+        used
+    }
+}
+
+/// Field layout of a topic type, in CDR order.
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub(crate) struct TypeLayout {
+    pub(crate) type_name: String,
+    pub(crate) fields: Vec<FieldLayout>,
+    /// Structs and enums used by the fields
+    #[serde(skip_serializing_if = "TypeDefs::is_empty")]
+    pub(crate) types: TypeDefs,
+}
+
+impl TypeLayout {
+    /// Checks a JSON object against the layout and returns its values in field order.
+    pub(crate) fn values_from_json(&self, json: &Value) -> Result<Vec<Value>, String> {
+        check_struct(&self.types, &self.fields, json, "")?;
+        let object = json.as_object().expect("checked above");
+        Ok(self
+            .fields
+            .iter()
+            .map(|field| object[&field.name].clone())
+            .collect())
+    }
+
+    pub(crate) fn values_to_json(&self, values: &[Value]) -> Value {
+        Value::Object(
+            self.fields
+                .iter()
+                .zip(values)
+                .map(|(field, value)| (field.name.clone(), value.clone()))
+                .collect::<Map<String, Value>>(),
+        )
+    }
+
+    /// Values of the key fields, in field order.
+    pub(crate) fn key_values(&self, values: &[Value]) -> Vec<Value> {
+        self.fields
+            .iter()
+            .zip(values)
+            .filter(|(field, _)| field.key)
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    /// Instance name used for storage and in URLs. A single key field is named by its value,
+    /// a string as is like the `sensor_type` key of SensorConfig. Several key fields are named
+    /// by the JSON array of their values, so ("a/b", "c") and ("a", "b/c") stay apart.
+    pub(crate) fn instance_name(&self, key: &[Value]) -> String {
+        match key {
+            [Value::String(s)] => s.clone(),
+            [value] => value.to_string(),
+            values => Value::Array(values.to_vec()).to_string(),
+        }
+    }
+
+    fn key_fields(&self) -> Vec<FieldLayout> {
+        self.fields.iter().filter(|field| field.key).cloned().collect()
+    }
+
+    /// Serializes key values as the CDR struct of the key fields, which is what the key hash
+    /// is computed from.
+    pub(crate) fn serialize_key<S: Serializer>(
+        &self,
+        key: &[Value],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Fields {
+            types: &self.types,
+            fields: &self.key_fields(),
+            values: FieldValues::Ordered(key),
+        }
+        .serialize(serializer)
+    }
+
+    /// Deserializes the CDR struct of the key fields into key values.
+    pub(crate) fn deserialize_key<'de, D: Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<Vec<Value>, D::Error> {
+        let fields = self.key_fields();
+        deserializer.deserialize_tuple(
+            fields.len(),
+            StructVisitor {
+                types: &self.types,
+                fields: &fields,
+            },
+        )
+    }
+
+    /// Largest CDR size of the key fields, alignment padding included. Keys of at most 16
+    /// bytes are their own key hash, larger ones are hashed with MD5.
+    pub(crate) fn key_max_size(&self) -> CdrEncodingMaxSize {
+        let end = self
+            .fields
+            .iter()
+            .filter(|field| field.key)
+            .try_fold(0, |offset, field| max_end(&self.types, &field.field_type, offset, &mut vec![]));
+        match end {
+            Some(size) => CdrEncodingMaxSize::Bytes(size),
+            None => CdrEncodingMaxSize::Unbounded,
+        }
+    }
+
+    /// Serializes values in field order as a CDR struct.
+    pub(crate) fn serialize_values<S: Serializer>(
+        &self,
+        values: &[Value],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Fields {
+            types: &self.types,
+            fields: &self.fields,
+            values: FieldValues::Ordered(values),
+        }
+        .serialize(serializer)
+    }
+
+    /// Deserializes a CDR struct into values in field order.
+    pub(crate) fn deserialize_values<'de, D: Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<Vec<Value>, D::Error> {
+        deserializer.deserialize_tuple(
+            self.fields.len(),
+            StructVisitor {
+                types: &self.types,
+                fields: &self.fields,
+            },
+        )
+    }
+
+    /// Adds the JSON schema of this type and of the types it uses to the OpenAPI components.
+    pub(crate) fn add_schemas(&self, openapi: &mut OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.schemas.insert(
+            schema_name(&self.type_name),
+            RefOr::T(struct_schema(&self.fields)),
+        );
+        for (name, fields) in &self.types.structs {
+            components
+                .schemas
+                .insert(schema_name(name), RefOr::T(struct_schema(fields)));
+        }
+        for (name, variants) in &self.types.enums {
+            components.schemas.insert(
+                schema_name(name),
+                RefOr::T(Schema::Object(
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::String)
+                        .enum_values(Some(variants.clone()))
+                        .build(),
+                )),
+            );
+        }
+    }
+}
+
+/// Largest offset at which a CDR value starting at `offset` ends, None when unbounded. `seen`
+/// guards against recursive structs.
+fn max_end<'a>(
+    types: &'a TypeDefs,
+    field_type: &'a FieldType,
+    offset: usize,
+    seen: &mut Vec<&'a str>,
+) -> Option<usize> {
+    // primitives are aligned to their size
+    let primitive = |size: usize| offset.checked_next_multiple_of(size)?.checked_add(size);
+    match field_type {
+        FieldType::Bool | FieldType::U8 | FieldType::I8 => primitive(1),
+        FieldType::U16 | FieldType::I16 => primitive(2),
+        FieldType::U32 | FieldType::I32 | FieldType::F32 | FieldType::Enum { .. } => primitive(4),
+        FieldType::U64 | FieldType::I64 | FieldType::F64 => primitive(8),
+        FieldType::String | FieldType::Sequence { bound: None, .. } => None,
+        FieldType::Sequence { element, bound: Some(bound) } => {
+            repeated_end(types, element, primitive(4)?, *bound, seen)
+        }
+        FieldType::Array { element, length } => repeated_end(types, element, offset, *length, seen),
+        FieldType::Struct { name } => {
+            let fields = types.structs.get(name)?;
+            if seen.contains(&name.as_str()) {
+                return None;
+            }
+            seen.push(name);
+            let end = fields
+                .iter()
+                .try_fold(offset, |offset, field| max_end(types, &field.field_type, offset, seen));
+            seen.pop();
+            end
+        }
+    }
+}
+
+/// Largest end of `count` values of one type written back to back. No type aligns to more
+/// than 8 bytes, so once an element starts at the same offset modulo 8 as an earlier one the
+/// elements in between repeat with the same growth and need not be walked one by one.
+fn repeated_end<'a>(
+    types: &'a TypeDefs,
+    element: &'a FieldType,
+    mut offset: usize,
+    count: u32,
+    seen: &mut Vec<&'a str>,
+) -> Option<usize> {
+    let count = count as usize;
+    let mut starts: Vec<usize> = vec![];
+    while starts.len() < count {
+        if let Some(first) = starts.iter().position(|start| start % 8 == offset % 8) {
+            let period = starts.len() - first;
+            let remaining = count - starts.len();
+            let growth = offset - starts[first];
+            offset = offset.checked_add((remaining / period).checked_mul(growth)?)?;
+            for _ in 0..remaining % period {
+                offset = max_end(types, element, offset, seen)?;
+            }
+            return Some(offset);
+        }
+        starts.push(offset);
+        offset = max_end(types, element, offset, seen)?;
+    }
+    Some(offset)
+}
+
+fn check_struct(types: &TypeDefs, fields: &[FieldLayout], json: &Value, path: &str) -> Result<(), String> {
+    let Some(object) = json.as_object() else {
+        return Err(format!("{} must be a JSON object", display_path(path)));
+    };
+    if let Some(unknown) = object
+        .keys()
+        .find(|name| !fields.iter().any(|field| &field.name == *name))
+    {
+        return Err(format!("unknown field \"{}\"", join_path(path, unknown)));
+    }
+    for field in fields {
+        let field_path = join_path(path, &field.name);
+        let value = object
+            .get(&field.name)
+            .ok_or_else(|| format!("missing field \"{field_path}\""))?;
+        check_value(types, &field.field_type, value, &field_path)?;
+    }
+    Ok(())
+}
+
+fn check_value(types: &TypeDefs, field_type: &FieldType, value: &Value, path: &str) -> Result<(), String> {
+    fn in_range<T: TryFrom<i64> + TryFrom<u64>>(value: &Value) -> bool {
+        value.as_i64().is_some_and(|v| T::try_from(v).is_ok())
+            || value.as_u64().is_some_and(|v| T::try_from(v).is_ok())
+    }
+    let valid = match field_type {
+        FieldType::Bool => value.is_boolean(),
+        FieldType::U8 => in_range::<u8>(value),
+        FieldType::I8 => in_range::<i8>(value),
+        FieldType::U16 => in_range::<u16>(value),
+        FieldType::I16 => in_range::<i16>(value),
+        FieldType::U32 => in_range::<u32>(value),
+        FieldType::I32 => in_range::<i32>(value),
+        FieldType::U64 => value.is_u64(),
+        FieldType::I64 => value.is_i64(),
+        FieldType::F32 | FieldType::F64 => value.is_number(),
+        FieldType::String => value.is_string(),
+        FieldType::Sequence { element, bound } => {
+            let Some(items) = value.as_array() else {
+                return Err(format!("field \"{path}\" must be an array"));
+            };
+            if let Some(bound) = bound {
+                if items.len() > *bound as usize {
+                    return Err(format!("field \"{path}\" holds at most {bound} items"));
+                }
+            }
+            for (index, item) in items.iter().enumerate() {
+                check_value(types, element, item, &format!("{path}[{index}]"))?;
+            }
+            true
+        }
+        FieldType::Array { element, length } => {
+            let Some(items) = value.as_array() else {
+                return Err(format!("field \"{path}\" must be an array"));
+            };
+            if items.len() != *length as usize {
+                return Err(format!("field \"{path}\" must hold exactly {length} items"));
+            }
+            for (index, item) in items.iter().enumerate() {
+                check_value(types, element, item, &format!("{path}[{index}]"))?;
+            }
+            true
+        }
+        FieldType::Enum { name } => value
+            .as_str()
+            .is_some_and(|variant| types.enums.get(name).is_some_and(|variants| variants.iter().any(|v| v == variant))),
+        FieldType::Struct { name } => {
+            let fields = types
+                .structs
+                .get(name)
+                .ok_or_else(|| format!("field \"{path}\" uses unknown struct {name}"))?;
+            return check_struct(types, fields, value, path);
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("field \"{path}\" is not a valid {}", type_label(field_type)))
+    }
+}
+
+fn type_label(field_type: &FieldType) -> String {
+    match field_type {
+        FieldType::Enum { name } | FieldType::Struct { name } => name.clone(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+fn display_path(path: &str) -> String {
+    if path.is_empty() {
+        String::from("body")
+    } else {
+        format!("field \"{path}\"")
+    }
+}
+
+/// OpenAPI component names may not contain `::`.
+pub(crate) fn schema_name(type_name: &str) -> String {
+    type_name.replace("::", ".")
+}
+
+fn struct_schema(fields: &[FieldLayout]) -> Schema {
+    let mut object = ObjectBuilder::new().schema_type(SchemaType::Object);
+    for field in fields {
+        object = object
+            .property(&field.name, field_schema(&field.field_type))
+            .required(&field.name);
+    }
+    Schema::Object(object.build())
+}
+
+fn field_schema(field_type: &FieldType) -> RefOr<Schema> {
+    let integer = |format: KnownFormat, minimum: f64, maximum: f64| {
+        ObjectBuilder::new()
+            .schema_type(SchemaType::Integer)
+            .format(Some(SchemaFormat::KnownFormat(format)))
+            .minimum(Some(minimum))
+            .maximum(Some(maximum))
+            .into()
+    };
+    match field_type {
+        FieldType::Bool => ObjectBuilder::new().schema_type(SchemaType::Boolean).into(),
+        FieldType::U8 => integer(KnownFormat::Int32, 0.0, u8::MAX as f64),
+        FieldType::I8 => integer(KnownFormat::Int32, i8::MIN as f64, i8::MAX as f64),
+        FieldType::U16 => integer(KnownFormat::Int32, 0.0, u16::MAX as f64),
+        FieldType::I16 => integer(KnownFormat::Int32, i16::MIN as f64, i16::MAX as f64),
+        FieldType::U32 => integer(KnownFormat::Int64, 0.0, u32::MAX as f64),
+        FieldType::I32 => integer(KnownFormat::Int32, i32::MIN as f64, i32::MAX as f64),
+        FieldType::U64 => integer(KnownFormat::Int64, 0.0, u64::MAX as f64),
+        FieldType::I64 => integer(KnownFormat::Int64, i64::MIN as f64, i64::MAX as f64),
+        FieldType::F32 => ObjectBuilder::new()
+            .schema_type(SchemaType::Number)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Float)))
+            .into(),
+        FieldType::F64 => ObjectBuilder::new()
+            .schema_type(SchemaType::Number)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Double)))
+            .into(),
+        FieldType::String => ObjectBuilder::new().schema_type(SchemaType::String).into(),
+        FieldType::Sequence { element, bound } => ArrayBuilder::new()
+            .items(field_schema(element))
+            .max_items(bound.map(|bound| bound as usize))
+            .into(),
+        FieldType::Array { element, length } => ArrayBuilder::new()
+            .items(field_schema(element))
+            .min_items(Some(*length as usize))
+            .max_items(Some(*length as usize))
+            .into(),
+        FieldType::Enum { name } | FieldType::Struct { name } => {
+            Ref::from_schema_name(schema_name(name)).into()
+        }
+    }
+}
+
+enum FieldValues<'a> {
+    /// Top-level values, already in field order
+    Ordered(&'a [Value]),
+    /// Nested struct as a JSON object
+    Object(&'a Map<String, Value>),
+}
+
+/// CDR struct: the fields one after another, without any header.
+struct Fields<'a> {
+    types: &'a TypeDefs,
+    fields: &'a [FieldLayout],
+    values: FieldValues<'a>,
+}
+
+impl Serialize for Fields<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(self.fields.len())?;
+        for (index, field) in self.fields.iter().enumerate() {
+            let value = match &self.values {
+                FieldValues::Ordered(values) => values.get(index),
+                FieldValues::Object(object) => object.get(&field.name),
+            }
+            .ok_or_else(|| S::Error::custom(format!("missing field {}", field.name)))?;
+            tuple.serialize_element(&Typed {
+                types: self.types,
+                field_type: &field.field_type,
+                value,
+            })?;
+        }
+        tuple.end()
+    }
+}
+
+struct Typed<'a> {
+    types: &'a TypeDefs,
+    field_type: &'a FieldType,
+    value: &'a Value,
+}
+
+impl Serialize for Typed<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let invalid = || S::Error::custom(format!("invalid {} value", type_label(self.field_type)));
+        let value = self.value;
+        match self.field_type {
+            FieldType::Bool => serializer.serialize_bool(value.as_bool().ok_or_else(invalid)?),
+            FieldType::U8 => serializer.serialize_u8(value.as_u64().ok_or_else(invalid)? as u8),
+            FieldType::I8 => serializer.serialize_i8(value.as_i64().ok_or_else(invalid)? as i8),
+            FieldType::U16 => serializer.serialize_u16(value.as_u64().ok_or_else(invalid)? as u16),
+            FieldType::I16 => serializer.serialize_i16(value.as_i64().ok_or_else(invalid)? as i16),
+            FieldType::U32 => serializer.serialize_u32(value.as_u64().ok_or_else(invalid)? as u32),
+            FieldType::I32 => serializer.serialize_i32(value.as_i64().ok_or_else(invalid)? as i32),
+            FieldType::U64 => serializer.serialize_u64(value.as_u64().ok_or_else(invalid)?),
+            FieldType::I64 => serializer.serialize_i64(value.as_i64().ok_or_else(invalid)?),
+            FieldType::F32 => serializer.serialize_f32(value.as_f64().ok_or_else(invalid)? as f32),
+            FieldType::F64 => serializer.serialize_f64(value.as_f64().ok_or_else(invalid)?),
+            FieldType::String => serializer.serialize_str(value.as_str().ok_or_else(invalid)?),
+            FieldType::Sequence { element, .. } => {
+                let items = value.as_array().ok_or_else(invalid)?;
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&Typed {
+                        types: self.types,
+                        field_type: element,
+                        value: item,
+                    })?;
+                }
+                seq.end()
+            }
+            FieldType::Array { element, length } => {
+                let items = value.as_array().ok_or_else(invalid)?;
+                let mut tuple = serializer.serialize_tuple(*length as usize)?;
+                for item in items {
+                    tuple.serialize_element(&Typed {
+                        types: self.types,
+                        field_type: element,
+                        value: item,
+                    })?;
+                }
+                tuple.end()
+            }
+            // CDR encodes an enum as the 32 bit index of its variant
+            FieldType::Enum { name } => {
+                let variant = value.as_str().ok_or_else(invalid)?;
+                let index = self
+                    .types
+                    .enums
+                    .get(name)
+                    .and_then(|variants| variants.iter().position(|v| v == variant))
+                    .ok_or_else(invalid)?;
+                serializer.serialize_u32(index as u32)
+            }
+            FieldType::Struct { name } => {
+                let fields = self.types.structs.get(name).ok_or_else(invalid)?;
+                Fields {
+                    types: self.types,
+                    fields,
+                    values: FieldValues::Object(value.as_object().ok_or_else(invalid)?),
+                }
+                .serialize(serializer)
+            }
+        }
+    }
+}
+
+struct TypedSeed<'a> {
+    types: &'a TypeDefs,
+    field_type: &'a FieldType,
+}
+
+impl<'de> DeserializeSeed<'de> for TypedSeed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        fn primitive<'de, T: Deserialize<'de> + Into<Value>, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Value, D::Error> {
+            T::deserialize(deserializer).map(Into::into)
+        }
+        match self.field_type {
+            FieldType::Bool => primitive::<bool, D>(deserializer),
+            FieldType::U8 => primitive::<u8, D>(deserializer),
+            FieldType::I8 => primitive::<i8, D>(deserializer),
+            FieldType::U16 => primitive::<u16, D>(deserializer),
+            FieldType::I16 => primitive::<i16, D>(deserializer),
+            FieldType::U32 => primitive::<u32, D>(deserializer),
+            FieldType::I32 => primitive::<i32, D>(deserializer),
+            FieldType::U64 => primitive::<u64, D>(deserializer),
+            FieldType::I64 => primitive::<i64, D>(deserializer),
+            FieldType::F32 => primitive::<f32, D>(deserializer),
+            FieldType::F64 => primitive::<f64, D>(deserializer),
+            FieldType::String => primitive::<String, D>(deserializer),
+            FieldType::Sequence { element, .. } => deserializer.deserialize_seq(ItemsVisitor {
+                types: self.types,
+                element,
+                length: None,
+            }),
+            FieldType::Array { element, length } => deserializer.deserialize_tuple(
+                *length as usize,
+                ItemsVisitor {
+                    types: self.types,
+                    element,
+                    length: Some(*length as usize),
+                },
+            ),
+            FieldType::Enum { name } => {
+                let index = u32::deserialize(deserializer)?;
+                self.types
+                    .enums
+                    .get(name)
+                    .and_then(|variants| variants.get(index as usize))
+                    .map(|variant| Value::String(variant.clone()))
+                    .ok_or_else(|| de::Error::custom(format!("invalid {name} index {index}")))
+            }
+            FieldType::Struct { name } => {
+                let fields = self
+                    .types
+                    .structs
+                    .get(name)
+                    .ok_or_else(|| de::Error::custom(format!("unknown struct {name}")))?;
+                let values = deserializer.deserialize_tuple(
+                    fields.len(),
+                    StructVisitor {
+                        types: self.types,
+                        fields,
+                    },
+                )?;
+                Ok(Value::Object(
+                    fields
+                        .iter()
+                        .map(|field| field.name.clone())
+                        .zip(values)
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+struct StructVisitor<'a> {
+    types: &'a TypeDefs,
+    fields: &'a [FieldLayout],
+}
+
+impl<'de> Visitor<'de> for StructVisitor<'_> {
+    type Value = Vec<Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a struct with {} fields", self.fields.len())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::with_capacity(self.fields.len());
+        for (index, field) in self.fields.iter().enumerate() {
+            let value = seq
+                .next_element_seed(TypedSeed {
+                    types: self.types,
+                    field_type: &field.field_type,
+                })?
+                .ok_or_else(|| de::Error::invalid_length(index, &self))?;
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+struct ItemsVisitor<'a> {
+    types: &'a TypeDefs,
+    element: &'a FieldType,
+    /// Fixed length of an array, `None` for a sequence
+    length: Option<usize>,
+}
+
+impl<'de> Visitor<'de> for ItemsVisitor<'_> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.length {
+            Some(length) => write!(formatter, "an array of {length} items"),
+            None => write!(formatter, "a sequence"),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = vec![];
+        loop {
+            if self.length == Some(items.len()) {
+                break;
+            }
+            let item = seq.next_element_seed(TypedSeed {
+                types: self.types,
+                field_type: self.element,
+            })?;
+            match item {
+                Some(item) => items.push(item),
+                None if self.length.is_none() => break,
+                None => return Err(de::Error::invalid_length(items.len(), &self)),
+            }
+        }
+        Ok(Value::Array(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::BigEndian;
+    use rustdds::{CdrDeserializer, CdrSerializer};
+    use serde_json::json;
+
+    fn field(name: &str, field_type: FieldType, key: bool) -> FieldLayout {
+        FieldLayout {
+            name: name.to_string(),
+            field_type,
+            key,
+        }
+    }
+
+    fn layout(fields: Vec<FieldLayout>) -> TypeLayout {
+        TypeLayout {
+            type_name: "Test".to_string(),
+            fields,
+            types: TypeDefs::default(),
+        }
+    }
+
+    fn encode_key(layout: &TypeLayout, key: &[Value]) -> Vec<u8> {
+        let mut bytes = vec![];
+        layout
+            .serialize_key(key, &mut CdrSerializer::<_, BigEndian>::new(&mut bytes))
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn numeric_key_is_encoded_as_its_cdr_type() {
+        let layout = layout(vec![
+            field("id", FieldType::U32, true),
+            field("celsius", FieldType::F32, false),
+        ]);
+        let key = layout.key_values(&[json!(7), json!(21.5)]);
+        assert_eq!(key, vec![json!(7)]);
+        assert_eq!(encode_key(&layout, &key), vec![0, 0, 0, 7]);
+        assert_eq!(layout.key_max_size(), CdrEncodingMaxSize::Bytes(4));
+        assert_eq!(layout.instance_name(&key), "7");
+    }
+
+    #[test]
+    fn multi_field_keys_do_not_collide() {
+        let layout = layout(vec![
+            field("site", FieldType::String, true),
+            field("engine", FieldType::String, true),
+        ]);
+        let first = [json!("a/b"), json!("c")];
+        let second = [json!("a"), json!("b/c")];
+        assert_ne!(encode_key(&layout, &first), encode_key(&layout, &second));
+        assert_ne!(layout.instance_name(&first), layout.instance_name(&second));
+        assert_eq!(layout.instance_name(&first), r#"["a/b","c"]"#);
+        assert_eq!(layout.key_max_size(), CdrEncodingMaxSize::Unbounded);
+    }
+
+    #[test]
+    fn single_string_key_is_named_by_itself() {
+        let layout = layout(vec![field("sensor_type", FieldType::String, true)]);
+        assert_eq!(layout.instance_name(&[json!("radar")]), "radar");
+        // length with the terminating nul, then the bytes
+        assert_eq!(
+            encode_key(&layout, &[json!("ab")]),
+            vec![0, 0, 0, 3, b'a', b'b', 0]
+        );
+    }
+
+    #[test]
+    fn key_round_trips_through_cdr() {
+        let layout = layout(vec![
+            field("celsius", FieldType::F64, false),
+            field("engine", FieldType::U16, true),
+            field("bank", FieldType::String, true),
+        ]);
+        let key = layout.key_values(&[json!(80.5), json!(3), json!("left")]);
+        let bytes = encode_key(&layout, &key);
+        let decoded = layout
+            .deserialize_key(&mut CdrDeserializer::<BigEndian>::new(&bytes))
+            .unwrap();
+        assert_eq!(decoded, key);
+    }
+
+    #[test]
+    fn bounded_key_sizes_add_up() {
+        let layout = layout(vec![
+            field("bank", FieldType::U8, true),
+            field("id", FieldType::U64, true),
+            field(
+                "slots",
+                FieldType::Sequence {
+                    element: Box::new(FieldType::U8),
+                    bound: Some(4),
+                },
+                true,
+            ),
+            field("data", FieldType::String, false),
+        ]);
+        // 1 + 7 padding + 8, then the length and the bytes
+        assert_eq!(layout.key_max_size(), CdrEncodingMaxSize::Bytes(24));
+        let key = [json!(1), json!(2), json!([1, 2, 3, 4])];
+        assert_eq!(encode_key(&layout, &key).len(), 24);
+    }
+
+    #[test]
+    fn misaligned_key_size_includes_padding() {
+        let layout = layout(vec![
+            field("bank", FieldType::U8, true),
+            field("id", FieldType::U64, true),
+            field("slot", FieldType::U32, true),
+        ]);
+        // without padding 13 bytes would fit the key hash and truncate the key
+        assert_eq!(layout.key_max_size(), CdrEncodingMaxSize::Bytes(20));
+        assert_eq!(encode_key(&layout, &[json!(1), json!(2), json!(3)]).len(), 20);
+    }
+
+    #[test]
+    fn repeated_key_elements_are_padded_like_their_encoding() {
+        let mut layout = layout(vec![
+            field("bank", FieldType::U8, true),
+            field(
+                "slots",
+                FieldType::Sequence {
+                    element: Box::new(FieldType::Struct { name: "Slot".to_string() }),
+                    bound: Some(1001),
+                },
+                true,
+            ),
+            field(
+                "tail",
+                FieldType::Array {
+                    element: Box::new(FieldType::U16),
+                    length: 3,
+                },
+                true,
+            ),
+        ]);
+        layout.types.structs.insert(
+            "Slot".to_string(),
+            vec![
+                field("kind", FieldType::U8, false),
+                field("level", FieldType::U16, false),
+                field("mask", FieldType::U8, false),
+            ],
+        );
+        let slots = vec![json!({"kind": 1, "level": 2, "mask": 3}); 1001];
+        let key = [json!(1), Value::Array(slots), json!([1, 2, 3])];
+        let size = encode_key(&layout, &key).len();
+        assert_eq!(layout.key_max_size(), CdrEncodingMaxSize::Bytes(size));
+    }
+
+    fn engine_layout() -> TypeLayout {
+        let mut types = TypeDefs::default();
+        types.enums.insert(
+            "engine::Mode".to_string(),
+            vec!["IDLE".to_string(), "RUNNING".to_string()],
+        );
+        types.structs.insert(
+            "engine::Position".to_string(),
+            vec![
+                field("lat", FieldType::F64, false),
+                field("lon", FieldType::F64, false),
+            ],
+        );
+        TypeLayout {
+            type_name: "engine::Engine".to_string(),
+            fields: vec![
+                field("id", FieldType::U8, true),
+                field("rpm", FieldType::U32, false),
+                field("mode", FieldType::Enum { name: "engine::Mode".to_string() }, false),
+                field(
+                    "position",
+                    FieldType::Struct { name: "engine::Position".to_string() },
+                    false,
+                ),
+                field(
+                    "samples",
+                    FieldType::Sequence {
+                        element: Box::new(FieldType::I16),
+                        bound: Some(8),
+                    },
+                    false,
+                ),
+                field(
+                    "limits",
+                    FieldType::Array {
+                        element: Box::new(FieldType::F32),
+                        length: 2,
+                    },
+                    false,
+                ),
+                field("label", FieldType::String, false),
+                field("valid", FieldType::Bool, false),
+            ],
+            types,
+        }
+    }
+
+    fn engine_json() -> Value {
+        json!({
+            "id": 3,
+            "rpm": 1200,
+            "mode": "RUNNING",
+            "position": { "lat": 60.5, "lon": 24.25 },
+            "samples": [-1, 0, 7],
+            "limits": [0.5, 99.5],
+            "label": "left",
+            "valid": true,
+        })
+    }
+
+    fn encode_values(layout: &TypeLayout, values: &[Value]) -> Vec<u8> {
+        let mut bytes = vec![];
+        layout
+            .serialize_values(values, &mut CdrSerializer::<_, BigEndian>::new(&mut bytes))
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn sample_round_trips_through_cdr() {
+        let layout = engine_layout();
+        let values = layout.values_from_json(&engine_json()).unwrap();
+        let bytes = encode_values(&layout, &values);
+        let decoded = layout
+            .deserialize_values(&mut CdrDeserializer::<BigEndian>::new(&bytes))
+            .unwrap();
+        assert_eq!(decoded, values);
+        assert_eq!(layout.values_to_json(&decoded), engine_json());
+    }
+
+    #[test]
+    fn sample_is_encoded_with_cdr_alignment() {
+        let layout = engine_layout();
+        let values = layout.values_from_json(&engine_json()).unwrap();
+        let bytes = encode_values(&layout, &values);
+        // u8 id, padding to 4, u32 rpm, then the enum as its u32 index
+        assert_eq!(&bytes[..12], &[3, 0, 0, 0, 0, 0, 0x04, 0xb0, 0, 0, 0, 1]);
+        // the f64 lat is aligned to 8
+        assert_eq!(&bytes[16..24], &60.5f64.to_be_bytes());
+    }
+
+    #[test]
+    fn invalid_samples_are_rejected() {
+        let layout = engine_layout();
+        let mut json = engine_json();
+        json["id"] = json!(256);
+        assert!(layout.values_from_json(&json).is_err());
+
+        let mut json = engine_json();
+        json["mode"] = json!("STOPPED");
+        assert!(layout.values_from_json(&json).is_err());
+
+        let mut json = engine_json();
+        json["limits"] = json!([1.0]);
+        assert!(layout.values_from_json(&json).is_err());
+
+        let mut json = engine_json();
+        json["position"].as_object_mut().unwrap().remove("lon");
+        let message = layout.values_from_json(&json).unwrap_err();
+        assert!(message.contains("position.lon"), "{message}");
+    }
+
+    #[test]
+    fn unknown_enum_index_fails_to_decode() {
+        let layout = engine_layout();
+        let mut values = layout.values_from_json(&engine_json()).unwrap();
+        values[2] = json!("IDLE");
+        let mut bytes = encode_values(&layout, &values);
+        bytes[11] = 9;
+        assert!(layout
+            .deserialize_values(&mut CdrDeserializer::<BigEndian>::new(&bytes))
+            .is_err());
+    }
+}
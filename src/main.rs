@@ -1,31 +1,113 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{
     extract::State,
-    http::StatusCode,
-    routing::{get, put},
-    Json, Router
+    http::{HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Router
 };
-use rustdds::with_key::Sample;
+use clap::Parser;
 use rustdds::*;
 use serde::{Deserialize, Serialize};
-use sled;
 use std::fmt::Debug;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tokio_stream::StreamExt;
+use std::sync::{Arc, OnceLock, RwLock};
+use tokio::sync::broadcast;
 use utoipa::OpenApi;
-use with_key::DataWriter;
 use utoipa::ToSchema;
 use utoipa_swagger_ui::SwaggerUi;
 
-type DataWriterState = Arc<Mutex<DataWriter<SensorConfig>>>;
+mod batch;
+mod config;
+mod confirm;
+mod dds;
+mod desired;
+mod discovery;
+mod drift;
+mod dynamic;
+mod error;
+mod extract;
+mod health;
+mod history;
+mod idl;
+mod layout;
+mod metrics;
+mod queue;
+mod reconcile;
+mod registry;
+mod retention;
+mod status;
+mod subscriber;
+mod validation;
+mod writer;
+mod ws;
+
+use batch::{BatchItemResult, BatchItemState, BatchReport, RollbackState};
+use config::{
+    Args, DurabilityConfig, GatewayConfig, HistoryConfig, LivelinessConfig, OwnershipConfig,
+    HealthConfig, QosProfile, ReliabilityConfig, ValidationConfig,
+};
+use confirm::{
+    Acknowledgement, AcknowledgedConfig, ConfigConfirmation, ConfigWriteQuery, ConfirmationState,
+};
+use dds::{Connector, DdsHandle, DdsState, DdsStatus, EntityQos, QosRegistry};
+use desired::{DesiredConfig, InstanceState};
+use discovery::{
+    EndpointKind, Liveliness, LivelinessChange, LocalEndpoint, MatchedEndpoint, RemoteEndpoint,
+    RemoteParticipant,
+};
+use drift::{FieldDrift, SensorDrift};
+use dynamic::{DynamicTopicInfo, DynamicTopics};
+use error::{ApiError, ErrorCode, Problem};
+use extract::{Json, Path, Query};
+use history::{AggregatePage, HistoryEntry, HistoryPage};
+use layout::{FieldLayout, FieldType, TypeDefs, TypeLayout};
+use metrics::Metrics;
+use queue::{QueueSink, QueuedConfig, UnmatchedPolicy};
+use reconcile::ReconcileSink;
+use retention::{FieldStats, Resolution, RetentionTrees, StatusAggregate};
+use status::{StatusEvent, StoredStatus};
+use health::{DatabaseHealth, DatabaseProbe, Health, Mode, SubscriberHealth, TopicHealth};
+use subscriber::{IngestionState, IngestionStateHandle, StatusSink};
+use validation::{FieldError, ValidationReport};
+use writer::{WriteFailure, WriteOptions, WriteOutcome, WriterHandle};
+
+use registry::{SensorRegistration, SensorRegistrationUpdate};
+
+/// Set once the DDS connection is established.
+type DataWriterState = Arc<OnceLock<WriterHandle>>;
+
+#[derive(Clone)]
+struct AppState {
+    status: sled::Tree,
+    history: sled::Tree,
+    aggregates_minute: sled::Tree,
+    aggregates_hour: sled::Tree,
+    registry: sled::Tree,
+    desired: sled::Tree,
+    queue: sled::Tree,
+    status_events: broadcast::Sender<StatusEvent>,
+    writer: DataWriterState,
+    ingestion: IngestionStateHandle,
+    qos: QosRegistry,
+    dynamic: DynamicTopics,
+    validation: Arc<ValidationConfig>,
+    health: Arc<HealthConfig>,
+    dds: DdsHandle,
+    database: DatabaseProbe,
+    metrics: Metrics,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
 struct SensorList {
-    name: String,    
-    path: String,    
+    sensor_type: String,
+    name: String,
+    path: String,
+    registered: bool,
+    seen: bool,
+    disposed: bool,
 }
-#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 struct SensorConfig {
     sensor_type: String,
     frequency: u32,
@@ -38,7 +120,16 @@ impl Keyed for SensorConfig {
         self.sensor_type.clone()
     }
 }
-#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+impl SensorConfig {
+    /// Whether the status reports the values of this config for the same sensor.
+    fn is_applied_by(&self, status: &SensorStatus) -> bool {
+        self.sensor_type == status.sensor_type
+            && self.frequency == status.frequency
+            && self.power == status.power
+            && self.squelti == status.squelti
+    }
+}
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 struct SensorStatus {
     sensor_type: String,
     frequency: u32,
@@ -52,76 +143,192 @@ impl Keyed for SensorStatus {
     }
 }
 
+/// IDL of SensorConfig and SensorStatus. Their field layouts and OpenAPI schemas are taken
+/// from it, the tests check that the structs above encode the same way.
+const SENSOR_IDL: &str = include_str!("../idl/sensor.idl");
+
+/// Layout of a compiled-in type declared in `SENSOR_IDL`, e.g. `sensors::SensorConfig`. It is
+/// named after the struct alone so that its schema replaces the one the handlers refer to.
+fn sensor_layout(idl_type: &str) -> TypeLayout {
+    static TYPES: OnceLock<TypeDefs> = OnceLock::new();
+    let types = TYPES.get_or_init(|| {
+        idl::parse_text(std::path::Path::new("idl/sensor.idl"), SENSOR_IDL)
+            .expect("idl/sensor.idl is checked by the tests")
+    });
+    let fields = types.structs[idl_type].clone();
+    TypeLayout {
+        type_name: idl_type.rsplit("::").next().unwrap_or(idl_type).to_string(),
+        types: types.referenced_by(&fields),
+        fields,
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // initialize tracing
     tracing_subscriber::fmt::init();
 
-    // dds
-    let domain_participant = DomainParticipantBuilder::new(0).build()?;
-    let qos = QosPolicyBuilder::new().build();
-    let topic_sensor_config = domain_participant
-        .create_topic(
-            "SensorConfig".to_string(),
-            "Topic: SensorConfig".to_string(),
-            &qos,
-            TopicKind::WithKey,
-        )
-        .unwrap();
-    let topic_name = "SensorStatus".to_string();
-    let topic_sensor_status = domain_participant
-        .create_topic(
-            topic_name.clone(),
-            "Topic: SensorStatus".to_string(),
-            &qos,
-            TopicKind::WithKey,
-        )
-        .unwrap();
-    let publisher = domain_participant.create_publisher(&qos).unwrap();
-    let writer = publisher
-        .create_datawriter_cdr::<SensorConfig>(&topic_sensor_config, Some(qos.clone()))
-        .unwrap();
-    let subscriber = domain_participant.create_subscriber(&qos).unwrap();
-    let reader = subscriber
-        .create_datareader_cdr::<SensorStatus>(&topic_sensor_status, Some(qos.clone()))
-        .unwrap();
-    let mut async_reader = reader.async_sample_stream();
+    // config
+    let args = Args::parse();
+    let config = GatewayConfig::load(&args)?;
+    println!("listening on {}, dds domain {}", config.bind_address, config.domain_id);
 
     // db
-    let db = sled::open(topic_name).unwrap();
-    let db_status = db.open_tree("status");
-    let db_clone_for_sub = db_status.clone().unwrap();
-    let db_clone_for_axum = db_status.clone().unwrap();
-
-    // background subscriber
-    tokio::spawn(async move {
-        println!("subscriber start");
-        while let Some(Ok(Sample::Value(v))) = &mut async_reader.next().await {
-            if let Ok(json) = serde_json::to_string(&v) {
-                db_clone_for_sub.insert(v.key(), json.as_bytes()).unwrap();
-                println!("subscribe: {:?}", v);
-            }
-        }
-    });
+    let database_path = config.database_path();
+    let db = sled::open(&database_path).with_context(|| {
+        format!(
+            "cannot open database {} (is another gateway instance using it?)",
+            database_path.display()
+        )
+    })?;
+    let open_tree = |name: &str| {
+        db.open_tree(name)
+            .with_context(|| format!("cannot open tree {name} of database {}", database_path.display()))
+    };
+    let db_status = open_tree("status")?;
+    let db_history = open_tree("history")?;
+    let db_aggregates_minute = open_tree("history_minute")?;
+    let db_aggregates_hour = open_tree("history_hour")?;
+    let db_registry = open_tree("registry")?;
+    let db_desired = open_tree("desired")?;
+    let db_queue = open_tree("config_queue")?;
+    let dynamic_topics = dynamic::prepare(&config, &db).context("cannot prepare dynamic topics")?;
+    let (status_events, _) = broadcast::channel(status::STATUS_EVENT_CAPACITY);
+    let ingestion = Arc::new(RwLock::new(IngestionState::default()));
+    let qos_registry = QosRegistry::default();
+    let dds = DdsHandle::default();
+    let metrics = Metrics::new().context("cannot register metrics")?;
+    let state_for_axum = AppState {
+        status: db_status.clone(),
+        history: db_history.clone(),
+        aggregates_minute: db_aggregates_minute.clone(),
+        aggregates_hour: db_aggregates_hour.clone(),
+        registry: db_registry,
+        desired: db_desired.clone(),
+        queue: db_queue.clone(),
+        status_events: status_events.clone(),
+        writer: Arc::default(),
+        ingestion: ingestion.clone(),
+        qos: qos_registry.clone(),
+        dynamic: dynamic_topics.clone(),
+        validation: Arc::new(config.validation.clone()),
+        health: Arc::new(config.health.clone()),
+        dds: dds.clone(),
+        database: DatabaseProbe::new(db.clone()),
+        metrics: metrics.clone(),
+    };
+
+    // dds, retried in the background while the HTTP server runs in degraded mode
+    tokio::spawn(dds::run_connector(Connector {
+        config: config.clone(),
+        handle: dds.clone(),
+        writer: state_for_axum.writer.clone(),
+        dynamic: dynamic_topics,
+        qos_registry: qos_registry.clone(),
+        metrics: metrics.clone(),
+        desired: db_desired.clone(),
+        status_sink: StatusSink {
+            db,
+            status: db_status.clone(),
+            history: db_history.clone(),
+            status_events,
+            ingestion,
+            qos_registry,
+            discovery: dds.discovery.clone(),
+            metrics: metrics.clone(),
+        },
+        reconcile_sink: ReconcileSink {
+            writer: state_for_axum.writer.clone(),
+            desired: db_desired.clone(),
+            status: db_status,
+        },
+        queue_sink: QueueSink {
+            writer: state_for_axum.writer.clone(),
+            queue: db_queue,
+        },
+    }));
 
-    // state
-    let writer_for_axum = Arc::new(Mutex::new(writer));
+    // retention
+    tokio::spawn(retention::run_retention(
+        RetentionTrees {
+            history: db_history,
+            minute: db_aggregates_minute,
+            hour: db_aggregates_hour,
+        },
+        config.retention.clone(),
+    ));
 
     // build our application with a route
     let mut doc = ApiDoc::openapi();
     doc.info.title = String::from("OpenAPI Documents");    
+    sensor_layout("sensors::SensorConfig").add_schemas(&mut doc);
+    sensor_layout("sensors::SensorStatus").add_schemas(&mut doc);
+    for topic in state_for_axum.dynamic.values() {
+        topic.layout.add_schemas(&mut doc);
+    }
     let app = Router::new()
-        .route("/sensor/list", get(get_handler_sensor_list))
         .route("/sensor/config", put(put_handler_sensor_config))
-        .with_state(writer_for_axum)
-        .route("/sensor/status", get(get_handler_sensor_status))
-        .with_state(db_clone_for_axum)
+        .route("/sensor/config/batch", put(batch::put_handler_sensor_config_batch))
+        .route(
+            "/sensor/config/validate",
+            post(validation::post_handler_sensor_config_validate),
+        )
+        .route("/sensor/config/queue", get(queue::get_handler_sensor_config_queue))
+        .route(
+            "/sensor/config/queue/:sensor_type",
+            delete(queue::delete_handler_sensor_config_queue),
+        )
+        .route(
+            "/sensor/config/:sensor_type",
+            delete(desired::delete_handler_sensor_config),
+        )
+        .route("/sensor/ws", get(ws::get_handler_sensor_ws))
+        .route("/sensor/list", get(get_handler_sensor_list))
+        .route("/sensor/drift", get(drift::get_handler_sensor_drift))
+        .route("/sensor/ingestion", get(subscriber::get_handler_sensor_ingestion))
+        .route("/dds/qos", get(dds::get_handler_dds_qos))
+        .route("/dds/participants", get(discovery::get_handler_dds_participants))
+        .route("/dds/matched", get(discovery::get_handler_dds_matched))
+        .route("/dds/liveliness", get(discovery::get_handler_dds_liveliness))
+        .route("/healthz", get(health::get_handler_healthz))
+        .route("/readyz", get(health::get_handler_readyz))
+        .route("/topics", get(dynamic::get_handler_topics))
+        .route(
+            "/topics/:topic",
+            get(dynamic::get_handler_topic).put(dynamic::put_handler_topic),
+        )
+        .route("/topics/:topic/*key", get(dynamic::get_handler_topic_instance))
+        .route("/sensor/status", get(get_handler_sensor_status_list))
+        .route("/sensor/status/stream", get(status::get_handler_sensor_status_stream))
+        .route("/sensor/status/:sensor_type", get(get_handler_sensor_status))
+        .route(
+            "/sensor/status/:sensor_type/history",
+            get(history::get_handler_sensor_status_history),
+        )
+        .route(
+            "/sensor/status/:sensor_type/aggregates",
+            get(history::get_handler_sensor_status_aggregates),
+        )
+        .route("/sensor/registry", post(registry::post_handler_sensor_registry))
+        .route(
+            "/sensor/registry/:sensor_type",
+            get(registry::get_handler_sensor_registry)
+                .patch(registry::patch_handler_sensor_registry)
+                .delete(registry::delete_handler_sensor_registry),
+        )
+        .route("/metrics", get(metrics::get_handler_metrics))
+        .route_layer(middleware::from_fn_with_state(metrics, metrics::track_http))
+        .fallback(error::fallback)
+        .layer(middleware::map_response(error::method_not_allowed))
+        .with_state(state_for_axum)
         .merge(SwaggerUi::new("/swagger-ui").url("/api-doc/openapi.json", doc));
 
-    let listener = tokio::net::TcpListener::bind("localhost:3000")
+    let listener = tokio::net::TcpListener::bind(&config.bind_address)
+        .await
+        .with_context(|| format!("cannot listen on {}", config.bind_address))?;
+    axum::serve(listener, app.into_make_service())
         .await
-        .unwrap();
-    axum::serve(listener, app.into_make_service()).await.unwrap();
+        .context("HTTP server failed")?;
 
     Ok(())
 }
@@ -131,71 +338,238 @@ async fn main() -> Result<()> {
     get,
     path = "/sensor/list",
     responses(
-        (status = 200, body = [SensorList], description = "Get sensors name"),        
+        (status = 200, body = [SensorList], description = "Get registered and seen sensors"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
     ),
     tag = "get_handler_sensor_list"
 )]
-async fn get_handler_sensor_list() -> (StatusCode, Json<Vec<SensorList>>) {
-    let api_list = Json(vec![
-        SensorList{
-            name: String::from("Sensor_Module_A"),
-            path: String::from("/sensor/module_A"),
-        },
-        SensorList{
-            name: String::from("Sensor_Module_B"),
-            path: String::from("/sensor/module_B"),
-        },
-    ]);
-    (StatusCode::OK, api_list)
+async fn get_handler_sensor_list(
+    State(db): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<SensorList>>), ApiError> {
+    let registrations = registry::load_registrations(&db.registry)?;
+    let mut api_list: Vec<SensorList> = registrations
+        .into_iter()
+        .map(|registration| SensorList {
+            path: format!("/sensor/status/{}", registration.sensor_type),
+            sensor_type: registration.sensor_type,
+            name: registration.name,
+            registered: true,
+            seen: false,
+            disposed: false,
+        })
+        .collect();
+
+    // sensor types seen on the SensorStatus topic
+    for item in db.status.iter() {
+        let (key, value) = item?;
+        let sensor_type = String::from_utf8_lossy(&key).to_string();
+        let disposed = serde_json::from_slice::<StoredStatus>(&value)
+            .map(|stored| stored.disposed)
+            .unwrap_or_default();
+        if let Some(sensor) = api_list.iter_mut().find(|s| s.sensor_type == sensor_type) {
+            sensor.seen = true;
+            sensor.disposed = disposed;
+        } else {
+            api_list.push(SensorList {
+                path: format!("/sensor/status/{}", sensor_type),
+                name: sensor_type.clone(),
+                sensor_type,
+                registered: false,
+                seen: true,
+                disposed,
+            });
+        }
+    }
+    Ok((StatusCode::OK, Json(api_list)))
+}
+
+/// Body of `PUT /sensor/config`, which one depends on the query and the outcome.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum ConfigWriteBody {
+    /// The written config, or the later config written instead when coalesced
+    Written(SensorConfig),
+    /// `unmatched=queue` and no reader is matched
+    Queued(QueuedConfig),
+    /// `ack=true`
+    Acknowledged(AcknowledgedConfig),
+    /// `wait=true`
+    Confirmation(ConfigConfirmation),
 }
 
 #[utoipa::path(
     put,
     path = "/sensor/config",
+    params(ConfigWriteQuery),
+    request_body = SensorConfig,
     responses(
-        (status = 200, body = [SensorConfig], description = "Set config to sensor"),
-        (status = 500, body = [SensorConfig], description = "Internal server error")
+        (status = 200, body = ConfigWriteBody, description = "Set config to sensor. \
+            The body is the SensorConfig, with `ack=true` an AcknowledgedConfig listing the acknowledging readers \
+            and with `wait=true` a ConfigConfirmation holding the applied status",
+            headers(
+                ("x-matched-readers" = usize, description = "Remote readers matched with the SensorConfig writer when writing"),
+                ("x-coalesced" = bool, description = "A later config for the same sensor was written instead, the body holds it")
+            )),
+        (status = 202, body = ConfigWriteBody, description = "`unmatched=queue`: no reader is matched, the body is the QueuedConfig. \
+            `ack=true`: an AcknowledgedConfig whose `timed_out` readers did not acknowledge in time. \
+            `wait=true`: a ConfigConfirmation whose sensor reported a status, but not yet with the written values, \
+            or that is not acknowledged by every reader"),
+        (status = 400, body = Problem, content_type = "application/problem+json", description = "`ack=true` on a SensorConfig topic without reliable QoS"),
+        (status = 422, body = Problem, content_type = "application/problem+json", description = "Config violates the capability profile of the sensor type, nothing is published"),
+        (status = 502, body = Problem, content_type = "application/problem+json", description = "DDS write failed"),
+        (status = 503, body = Problem, content_type = "application/problem+json", description = "DDS is not connected yet, the gateway runs in degraded mode, \
+            or `unmatched=reject` and no reader is matched"),
+        (status = 504, body = Problem, content_type = "application/problem+json", description = "`wait=true`: the sensor reported no status before the timeout, \
+            or the SensorConfig writer did not take the write within `writer.timeout_ms`")
     ),
     tag = "put_handler_sensor_config"
 )]
 async fn put_handler_sensor_config(
-    State(writer): State<DataWriterState>,
+    State(state): State<AppState>,
+    Query(query): Query<ConfigWriteQuery>,
     Json(payload): Json<SensorConfig>,
-) -> (StatusCode, Json<SensorConfig>) {
-    let writer = &mut writer.lock().await;
-    if let Ok(_) = writer.async_write(payload.clone(), None).await {
-        (StatusCode::OK, Json(payload))
-    } else {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(SensorConfig {
-                ..Default::default()
-            }),
-        )
+) -> Result<Response, ApiError> {
+    validation::validate_config(&state.validation, &payload).into_result()?;
+    let policy = query.unmatched.unwrap_or_default();
+    // subscribe before writing so that a fast reply is not missed
+    let status_events = state.status_events.subscribe();
+    let Some(writer) = state.writer.get() else {
+        if policy == UnmatchedPolicy::Queue {
+            let queued = queue::enqueue(&state.queue, &payload)?;
+            return Ok((StatusCode::ACCEPTED, Json(ConfigWriteBody::Queued(queued))).into_response());
+        }
+        return Err(ApiError::dds_unavailable());
+    };
+    let options = WriteOptions {
+        require_matched: policy != UnmatchedPolicy::Send,
+        ack: query.ack_timeout(),
+        record_desired: true,
+        // the caller waiting for this exact config must not get the outcome of another one
+        coalesce: query.ack_timeout().is_none() && query.wait_timeout().is_none(),
+    };
+    let written = match writer.write(payload.clone(), options).await {
+        Ok(written) => written,
+        Err(WriteFailure::NoMatchedReaders) if policy == UnmatchedPolicy::Queue => {
+            let queued = queue::enqueue(&state.queue, &payload)?;
+            return Ok(with_matched_readers(
+                (StatusCode::ACCEPTED, Json(ConfigWriteBody::Queued(queued))).into_response(),
+                0,
+            ));
+        }
+        Err(WriteFailure::NoMatchedReaders) => {
+            return Err(ApiError::new(
+                ErrorCode::NoMatchedReaders,
+                format!("no reader is matched to receive the config of {}", payload.sensor_type),
+            ))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let WriteOutcome {
+        matched_readers,
+        acknowledgement,
+        superseded_by,
+    } = written;
+    if let Some(written) = superseded_by {
+        let mut response = with_matched_readers(
+            (StatusCode::OK, Json(ConfigWriteBody::Written(written))).into_response(),
+            matched_readers,
+        );
+        response
+            .headers_mut()
+            .insert(writer::COALESCED_HEADER, HeaderValue::from_static("true"));
+        return Ok(response);
     }
+
+    let Some(timeout) = query.wait_timeout() else {
+        let response = match acknowledgement {
+            Some(acknowledgement) => {
+                let status_code = if acknowledgement.complete() {
+                    StatusCode::OK
+                } else {
+                    StatusCode::ACCEPTED
+                };
+                let acknowledged = AcknowledgedConfig {
+                    config: payload,
+                    acknowledgement,
+                };
+                (status_code, Json(ConfigWriteBody::Acknowledged(acknowledged))).into_response()
+            }
+            None => (StatusCode::OK, Json(ConfigWriteBody::Written(payload))).into_response(),
+        };
+        return Ok(with_matched_readers(response, matched_readers));
+    };
+    let mut confirmation = confirm::wait_for_status(status_events, payload, timeout).await;
+    let acknowledged = acknowledgement.as_ref().is_none_or(Acknowledgement::complete);
+    confirmation.acknowledgement = acknowledgement;
+    let status_code = match confirmation.state {
+        ConfirmationState::Applied if acknowledged => StatusCode::OK,
+        ConfirmationState::Applied | ConfirmationState::Pending => StatusCode::ACCEPTED,
+        ConfirmationState::TimedOut => {
+            return Err(ApiError::new(
+                ErrorCode::Timeout,
+                format!(
+                    "{} reported no status within {} ms",
+                    confirmation.config.sensor_type, confirmation.waited_ms
+                ),
+            ))
+        }
+    };
+    Ok(with_matched_readers(
+        (status_code, Json(ConfigWriteBody::Confirmation(confirmation))).into_response(),
+        matched_readers,
+    ))
+}
+
+fn with_matched_readers(mut response: Response, matched_readers: usize) -> Response {
+    response
+        .headers_mut()
+        .insert(queue::MATCHED_READERS_HEADER, HeaderValue::from(matched_readers));
+    response
 }
 #[utoipa::path(
     get,
     path = "/sensor/status",
     responses(
-        (status = 200, body = [SensorStatus], description = "Get status from sensor"),
-        (status = 500, body = [SensorStatus], description = "Internal server error")
+        (status = 200, body = [SensorStatus], description = "Get status of all sensors that are not disposed"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_sensor_status_list",
+)]
+async fn get_handler_sensor_status_list(
+    State(db): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<SensorStatus>>), ApiError> {
+    let mut status_list = vec![];
+    for item in db.status.iter() {
+        let (_, value) = item?;
+        match serde_json::from_slice::<StoredStatus>(&value) {
+            Ok(stored) if !stored.disposed => status_list.push(stored.status),
+            _ => {}
+        }
+    }
+    Ok((StatusCode::OK, Json(status_list)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/status/{sensor_type}",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)")
+    ),
+    responses(
+        (status = 200, body = SensorStatus, description = "Get status from sensor"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Sensor not found or disposed"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
     ),
     tag = "get_handler_sensor_status",
 )]
 async fn get_handler_sensor_status(
-    State(db_tree): State<sled::Tree>,
-) -> (StatusCode, Json<SensorConfig>) {
-    if let Ok(Some(value)) = db_tree.get("radio") {
-        let deser_json = serde_json::from_slice(&value).unwrap();
-        (StatusCode::OK, Json(deser_json))
-    } else {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(SensorConfig {
-                ..Default::default()
-            }),
-        )
+    State(db): State<AppState>,
+    Path(sensor_type): Path<String>,
+) -> Result<(StatusCode, Json<SensorStatus>), ApiError> {
+    match status::load_status(&db.status, &sensor_type)? {
+        Some(stored) if !stored.disposed => Ok((StatusCode::OK, Json(stored.status))),
+        Some(_) => Err(ApiError::not_found(format!("sensor {sensor_type} is disposed"))),
+        None => Err(ApiError::not_found(format!("sensor {sensor_type} has not reported a status"))),
     }
 }
 
@@ -204,13 +578,160 @@ async fn get_handler_sensor_status(
     paths(
         get_handler_sensor_list,
         put_handler_sensor_config,
-        get_handler_sensor_status,        
+        batch::put_handler_sensor_config_batch,
+        validation::post_handler_sensor_config_validate,
+        desired::delete_handler_sensor_config,
+        queue::get_handler_sensor_config_queue,
+        queue::delete_handler_sensor_config_queue,
+        drift::get_handler_sensor_drift,
+        get_handler_sensor_status_list,
+        get_handler_sensor_status,
+        status::get_handler_sensor_status_stream,
+        history::get_handler_sensor_status_history,
+        history::get_handler_sensor_status_aggregates,
+        ws::get_handler_sensor_ws,
+        subscriber::get_handler_sensor_ingestion,
+        dds::get_handler_dds_qos,
+        discovery::get_handler_dds_participants,
+        discovery::get_handler_dds_matched,
+        discovery::get_handler_dds_liveliness,
+        health::get_handler_healthz,
+        health::get_handler_readyz,
+        metrics::get_handler_metrics,
+        dynamic::get_handler_topics,
+        dynamic::put_handler_topic,
+        dynamic::get_handler_topic,
+        dynamic::get_handler_topic_instance,
+        registry::post_handler_sensor_registry,
+        registry::get_handler_sensor_registry,
+        registry::patch_handler_sensor_registry,
+        registry::delete_handler_sensor_registry,
     ),
     components(schemas(
         SensorList,
-        SensorConfig,
-        SensorStatus,        
+        ConfigWriteBody,
+        SensorRegistration,
+        SensorRegistrationUpdate,
+        IngestionState,
+        ConfigConfirmation,
+        Acknowledgement,
+        AcknowledgedConfig,
+        BatchReport,
+        BatchItemResult,
+        BatchItemState,
+        RollbackState,
+        DesiredConfig,
+        QueuedConfig,
+        UnmatchedPolicy,
+        InstanceState,
+        SensorDrift,
+        ValidationReport,
+        FieldError,
+        Problem,
+        ErrorCode,
+        FieldDrift,
+        ConfirmationState,
+        EntityQos,
+        DdsStatus,
+        DdsState,
+        RemoteParticipant,
+        RemoteEndpoint,
+        EndpointKind,
+        LocalEndpoint,
+        MatchedEndpoint,
+        LivelinessChange,
+        Liveliness,
+        Health,
+        Mode,
+        SubscriberHealth,
+        TopicHealth,
+        DatabaseHealth,
+        QosProfile,
+        ReliabilityConfig,
+        DurabilityConfig,
+        HistoryConfig,
+        LivelinessConfig,
+        OwnershipConfig,
+        DynamicTopicInfo,
+        TypeLayout,
+        FieldLayout,
+        FieldType,
+        TypeDefs,
+        HistoryEntry,
+        HistoryPage,
+        AggregatePage,
+        StatusAggregate,
+        FieldStats,
+        Resolution,
     )),
     tags((name = "Rust_WebDDS_Client", description="This is Sample Axum with DDS pub/sub"))
 )]
 struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::LittleEndian;
+    use serde_json::json;
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        value
+            .serialize(&mut CdrSerializer::<_, LittleEndian>::new(&mut bytes))
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn compiled_types_encode_like_their_idl() {
+        for idl_type in ["sensors::SensorConfig", "sensors::SensorStatus"] {
+            let layout = sensor_layout(idl_type);
+            let config = SensorConfig {
+                sensor_type: "radar".to_string(),
+                frequency: 2450,
+                power: 10,
+                squelti: 3,
+            };
+            let values = layout
+                .deserialize_values(&mut CdrDeserializer::<LittleEndian>::new(&encode(&config)))
+                .unwrap();
+            assert_eq!(layout.values_to_json(&values), serde_json::to_value(&config).unwrap());
+
+            let mut bytes = vec![];
+            layout
+                .serialize_values(&values, &mut CdrSerializer::<_, LittleEndian>::new(&mut bytes))
+                .unwrap();
+            let status = SensorStatus::deserialize(&mut CdrDeserializer::<LittleEndian>::new(&bytes)).unwrap();
+            assert!(config.is_applied_by(&status));
+            assert_eq!(layout.key_values(&values), vec![json!("radar")]);
+        }
+    }
+
+    #[test]
+    fn compiled_types_are_named_after_their_struct() {
+        let layout = sensor_layout("sensors::SensorConfig");
+        assert_eq!(layout.type_name, "SensorConfig");
+        assert_eq!(
+            layout.fields.iter().filter(|field| field.key).map(|field| field.name.as_str()).collect::<Vec<_>>(),
+            vec!["sensor_type"]
+        );
+    }
+
+    #[test]
+    fn config_write_documents_every_accepted_body() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let accepted = &doc["paths"]["/sensor/config"]["put"]["responses"]["202"];
+        assert_eq!(
+            accepted["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ConfigWriteBody"
+        );
+        let bodies = doc["components"]["schemas"]["ConfigWriteBody"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|body| body["$ref"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(bodies.contains(&"#/components/schemas/QueuedConfig"));
+        assert!(bodies.contains(&"#/components/schemas/ConfigConfirmation"));
+    }
+}
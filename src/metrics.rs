@@ -0,0 +1,228 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{ApiError, ErrorCode};
+use crate::history;
+use crate::AppState;
+
+/// Counting the entries of the sled trees walks all of them, so the gauges read from the
+/// database are refreshed at most this often instead of on every scrape.
+const DB_GAUGES_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Metrics of the gateway, exposed in Prometheus text format at `GET /metrics`.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    samples_received: IntCounterVec,
+    samples_written: IntCounterVec,
+    write_failures: IntCounterVec,
+    subscriber_restarts: IntCounter,
+    tree_entries: IntGaugeVec,
+    sensor_last_seen: GaugeVec,
+    writer_queue_depth: IntGauge,
+    writer_coalesced: IntCounter,
+    writer_timeouts: IntCounter,
+    /// When the gauges read from the database were last refreshed
+    db_gauges_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("webdds".to_string()), None)?;
+        let metrics = Metrics {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests by route and status"),
+                &["method", "route", "status"],
+            )?,
+            http_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route"),
+                &["method", "route"],
+            )?,
+            samples_received: IntCounterVec::new(
+                Opts::new("dds_samples_received_total", "DDS samples received by topic and key"),
+                &["topic", "key"],
+            )?,
+            samples_written: IntCounterVec::new(
+                Opts::new(
+                    "dds_samples_written_total",
+                    "DDS samples and disposes written by topic and key",
+                ),
+                &["topic", "key"],
+            )?,
+            write_failures: IntCounterVec::new(
+                Opts::new("dds_write_failures_total", "Failed DDS writes and disposes by topic"),
+                &["topic"],
+            )?,
+            subscriber_restarts: IntCounter::new(
+                "subscriber_restarts_total",
+                "Times the SensorStatus DataReader was recreated",
+            )?,
+            tree_entries: IntGaugeVec::new(
+                Opts::new("sled_tree_entries", "Entries of each sled tree"),
+                &["tree"],
+            )?,
+            sensor_last_seen: GaugeVec::new(
+                Opts::new(
+                    "sensor_last_seen_timestamp_seconds",
+                    "Receive time of the last SensorStatus sample of each sensor",
+                ),
+                &["sensor_type"],
+            )?,
+            writer_queue_depth: IntGauge::new(
+                "writer_queue_depth",
+                "Requests waiting for the SensorConfig writer task",
+            )?,
+            writer_coalesced: IntCounter::new(
+                "writer_coalesced_total",
+                "SensorConfig writes replaced by a later write of the same sensor",
+            )?,
+            writer_timeouts: IntCounter::new(
+                "writer_timeouts_total",
+                "SensorConfig writer requests that timed out",
+            )?,
+            registry,
+            db_gauges_at: Arc::default(),
+        };
+        metrics.registry.register(Box::new(metrics.http_requests.clone()))?;
+        metrics.registry.register(Box::new(metrics.http_duration.clone()))?;
+        metrics.registry.register(Box::new(metrics.samples_received.clone()))?;
+        metrics.registry.register(Box::new(metrics.samples_written.clone()))?;
+        metrics.registry.register(Box::new(metrics.write_failures.clone()))?;
+        metrics.registry.register(Box::new(metrics.subscriber_restarts.clone()))?;
+        metrics.registry.register(Box::new(metrics.tree_entries.clone()))?;
+        metrics.registry.register(Box::new(metrics.sensor_last_seen.clone()))?;
+        metrics.registry.register(Box::new(metrics.writer_queue_depth.clone()))?;
+        metrics.registry.register(Box::new(metrics.writer_coalesced.clone()))?;
+        metrics.registry.register(Box::new(metrics.writer_timeouts.clone()))?;
+        Ok(metrics)
+    }
+
+    pub(crate) fn record_received(&self, topic: &str, key: &str) {
+        self.samples_received.with_label_values(&[topic, key]).inc();
+    }
+
+    pub(crate) fn record_write(&self, topic: &str, key: &str, ok: bool) {
+        if ok {
+            self.samples_written.with_label_values(&[topic, key]).inc();
+        } else {
+            self.write_failures.with_label_values(&[topic]).inc();
+        }
+    }
+
+    pub(crate) fn record_subscriber_restart(&self) {
+        self.subscriber_restarts.inc();
+    }
+
+    pub(crate) fn record_writer_coalesced(&self) {
+        self.writer_coalesced.inc();
+    }
+
+    pub(crate) fn record_writer_timeout(&self) {
+        self.writer_timeouts.inc();
+    }
+
+    /// Sets the gauges that are read from the writer task and, when due, from the database.
+    fn update_gauges(&self, state: &AppState) -> sled::Result<()> {
+        if let Some(writer) = state.writer.get() {
+            self.writer_queue_depth.set(writer.queue_depth() as i64);
+        }
+        // held during the refresh so that concurrent scrapes do not walk the trees twice
+        let mut db_gauges_at = self.db_gauges_at.lock().unwrap_or_else(|e| e.into_inner());
+        if db_gauges_at.is_some_and(|at| at.elapsed() < DB_GAUGES_INTERVAL) {
+            return Ok(());
+        }
+        self.update_from_db(state)?;
+        *db_gauges_at = Some(Instant::now());
+        Ok(())
+    }
+
+    fn update_from_db(&self, state: &AppState) -> sled::Result<()> {
+        let mut trees = vec![
+            ("status", &state.status),
+            ("history", &state.history),
+            ("history_minute", &state.aggregates_minute),
+            ("history_hour", &state.aggregates_hour),
+            ("registry", &state.registry),
+            ("desired", &state.desired),
+            ("config_queue", &state.queue),
+        ];
+        let topic_trees = state
+            .dynamic
+            .iter()
+            .filter_map(|(name, topic)| Some((format!("topic/{name}"), topic.samples.as_ref()?)))
+            .collect::<Vec<_>>();
+        trees.extend(topic_trees.iter().map(|(name, tree)| (name.as_str(), *tree)));
+        for (name, tree) in trees {
+            self.tree_entries.with_label_values(&[name]).set(tree.len() as i64);
+        }
+
+        // removed sensors must not keep their last value
+        self.sensor_last_seen.reset();
+        for key in state.status.iter().keys() {
+            let sensor_type = String::from_utf8_lossy(&key?).to_string();
+            if let Some(received_at) = history::last_received_at(&state.history, &sensor_type)? {
+                self.sensor_last_seen
+                    .with_label_values(&[&sensor_type])
+                    .set(received_at as f64 / 1000.0);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Counts every request to a known route together with its latency.
+pub(crate) async fn track_http(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics
+        .http_duration
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    metrics
+        .http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, body = String, content_type = "text/plain", description = "Get metrics in Prometheus text format"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_metrics"
+)]
+pub(crate) async fn get_handler_metrics(State(state): State<AppState>) -> Result<Response, ApiError> {
+    state.metrics.update_gauges(&state)?;
+    let encoder = TextEncoder::new();
+    let mut body = vec![];
+    encoder
+        .encode(&state.metrics.registry.gather(), &mut body)
+        .map_err(|e| ApiError::new(ErrorCode::Internal, format!("cannot encode metrics: {e}")))?;
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        body,
+    )
+        .into_response())
+}
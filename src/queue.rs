@@ -0,0 +1,154 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::extract::{Json, Path};
+use crate::history::now_millis;
+use crate::writer::{WriteFailure, WriteOptions};
+use crate::{AppState, DataWriterState, SensorConfig};
+
+/// How often queued configs are checked for a matched reader.
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Response header holding the number of remote readers matched with the SensorConfig writer.
+pub(crate) const MATCHED_READERS_HEADER: &str = "x-matched-readers";
+
+/// What `PUT /sensor/config` does when no remote reader is matched with the SensorConfig writer.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UnmatchedPolicy {
+    /// Write anyway, the sample is lost unless the topic is durable
+    #[default]
+    Send,
+    /// Fail with 503
+    Reject,
+    /// Store the config and write it once a reader is matched
+    Queue,
+}
+
+/// Config waiting for a matched reader, stored in the `config_queue` sled tree keyed by
+/// `sensor_type`. A newer config for the same sensor replaces the queued one.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct QueuedConfig {
+    pub(crate) config: SensorConfig,
+    /// Milliseconds since the Unix epoch
+    pub(crate) queued_at: u64,
+}
+
+pub(crate) fn enqueue(tree: &sled::Tree, config: &SensorConfig) -> sled::Result<QueuedConfig> {
+    let queued = QueuedConfig {
+        config: config.clone(),
+        queued_at: now_millis(),
+    };
+    let json = serde_json::to_vec(&queued).expect("QueuedConfig serializes");
+    tree.insert(&config.sensor_type, json)?;
+    Ok(queued)
+}
+
+fn load_queue(tree: &sled::Tree) -> sled::Result<Vec<QueuedConfig>> {
+    let mut queued = vec![];
+    for item in tree.iter() {
+        let (_, value) = item?;
+        match serde_json::from_slice(&value) {
+            Ok(config) => queued.push(config),
+            Err(e) => println!("queue: skip undecodable entry: {e}"),
+        }
+    }
+    Ok(queued)
+}
+
+/// Everything the delivery task reads and writes.
+pub(crate) struct QueueSink {
+    pub(crate) writer: DataWriterState,
+    pub(crate) queue: sled::Tree,
+}
+
+impl QueueSink {
+    async fn deliver(&self) -> sled::Result<()> {
+        if self.queue.is_empty() {
+            return Ok(());
+        }
+        let Some(writer) = self.writer.get() else {
+            return Ok(());
+        };
+        let options = WriteOptions {
+            require_matched: true,
+            ack: None,
+            record_desired: true,
+            coalesce: false,
+        };
+        for item in self.queue.iter() {
+            let (key, value) = item?;
+            let Ok(queued) = serde_json::from_slice::<QueuedConfig>(&value) else {
+                continue;
+            };
+            let sensor_type = queued.config.sensor_type.clone();
+            match writer.write(queued.config.clone(), options.clone()).await {
+                Ok(_) => {}
+                // still no reader, try again later
+                Err(WriteFailure::NoMatchedReaders) => return Ok(()),
+                Err(e) => {
+                    println!("queue: cannot deliver config of {sensor_type}: {e}");
+                    continue;
+                }
+            }
+            println!("queue: delivered config of {sensor_type}");
+            // keep a config queued while this one was written
+            let _ = self.queue.compare_and_swap(key, Some(value), None as Option<&[u8]>)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes queued configs once a remote reader is matched with the SensorConfig writer.
+pub(crate) async fn run_queue(sink: QueueSink) {
+    let mut interval = tokio::time::interval(DELIVERY_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = sink.deliver().await {
+            println!("queue: {:?}", e);
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/config/queue",
+    responses(
+        (status = 200, body = [QueuedConfig], description = "Get configs waiting for a matched SensorConfig reader"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_sensor_config_queue"
+)]
+pub(crate) async fn get_handler_sensor_config_queue(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<Vec<QueuedConfig>>), ApiError> {
+    Ok((StatusCode::OK, Json(load_queue(&state.queue)?)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sensor/config/queue/{sensor_type}",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type")
+    ),
+    responses(
+        (status = 200, body = QueuedConfig, description = "Cancel the queued config, it will not be written"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "No config queued for the sensor"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "delete_handler_sensor_config_queue"
+)]
+pub(crate) async fn delete_handler_sensor_config_queue(
+    State(state): State<AppState>,
+    Path(sensor_type): Path<String>,
+) -> Result<(StatusCode, Json<QueuedConfig>), ApiError> {
+    let removed = state
+        .queue
+        .remove(&sensor_type)?
+        .and_then(|value| serde_json::from_slice(&value).ok())
+        .ok_or_else(|| ApiError::not_found(format!("no config queued for {sensor_type}")))?;
+    Ok((StatusCode::OK, Json(removed)))
+}
@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+
+use crate::config::ReconcileConfig;
+use crate::desired::{DesiredConfig, InstanceState};
+use crate::status::{self, StatusEvent, StoredStatus};
+use crate::writer::WriteOptions;
+use crate::{desired, DataWriterState, SensorConfig};
+
+/// Everything the reconciler reads and writes.
+pub(crate) struct ReconcileSink {
+    pub(crate) writer: DataWriterState,
+    pub(crate) desired: sled::Tree,
+    pub(crate) status: sled::Tree,
+}
+
+struct Reconciler {
+    sink: ReconcileSink,
+    /// Sensors whose latest status is not disposed
+    present: HashSet<String>,
+    /// When the desired config of a sensor was last re-sent
+    last_sent: HashMap<String, Instant>,
+    min_resend_interval: Duration,
+}
+
+impl Reconciler {
+    async fn send(&mut self, config: &SensorConfig, reason: &str) {
+        // the reconciler is started once DDS is connected, so the writer is set
+        let Some(writer) = self.sink.writer.get() else {
+            return;
+        };
+        let result = writer.write(config.clone(), WriteOptions::default()).await;
+        match result {
+            Ok(_) => println!("reconcile: re-sent config of {} ({reason})", config.sensor_type),
+            Err(e) => println!("reconcile: cannot re-send config of {}: {e}", config.sensor_type),
+        }
+        self.last_sent
+            .insert(config.sensor_type.clone(), Instant::now());
+    }
+
+    fn desired_config(&self, sensor_type: &str) -> Option<SensorConfig> {
+        match desired::load_desired(&self.sink.desired, sensor_type) {
+            // configs of disposed instances are not re-sent
+            Ok(Some(DesiredConfig {
+                config,
+                instance_state: InstanceState::Alive,
+                ..
+            })) => config,
+            Ok(_) => None,
+            Err(e) => {
+                println!("reconcile: {:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn republish_all(&mut self) {
+        match desired::load_alive(&self.sink.desired) {
+            Ok(configs) => {
+                for config in configs {
+                    self.send(&config, "startup").await;
+                }
+            }
+            Err(e) => println!("reconcile: {:?}", e),
+        }
+    }
+
+    /// Re-sends the desired config when a sensor shows up again after being disposed or absent.
+    async fn on_status_event(&mut self, event: StatusEvent) {
+        let sensor_type = event.status.sensor_type;
+        if event.disposed {
+            self.present.remove(&sensor_type);
+            return;
+        }
+        if self.present.insert(sensor_type.clone()) {
+            if let Some(config) = self.desired_config(&sensor_type) {
+                self.send(&config, "sensor reappeared").await;
+            }
+        }
+    }
+
+    /// Re-sends desired configs that the reported status does not match.
+    async fn check_drift(&mut self) {
+        let configs = match desired::load_alive(&self.sink.desired) {
+            Ok(configs) => configs,
+            Err(e) => {
+                println!("reconcile: {:?}", e);
+                return;
+            }
+        };
+        for config in configs {
+            let status = match status::load_status(&self.sink.status, &config.sensor_type) {
+                Ok(Some(StoredStatus {
+                    disposed: false,
+                    status,
+                    ..
+                })) => status,
+                // nothing to compare with until the sensor reports
+                Ok(_) => continue,
+                Err(e) => {
+                    println!("reconcile: {:?}", e);
+                    return;
+                }
+            };
+            let recently_sent = self
+                .last_sent
+                .get(&config.sensor_type)
+                .is_some_and(|sent| sent.elapsed() < self.min_resend_interval);
+            if !config.is_applied_by(&status) && !recently_sent {
+                self.send(&config, "status drifted").await;
+            }
+        }
+    }
+}
+
+/// Keeps the sensors on their desired config: republishes it at startup, when a sensor
+/// reappears on the SensorStatus topic and when the reported status drifts from it.
+pub(crate) async fn run_reconciler(
+    sink: ReconcileSink,
+    mut status_events: broadcast::Receiver<StatusEvent>,
+    config: ReconcileConfig,
+) {
+    let present = sink
+        .status
+        .iter()
+        .filter_map(|item| item.ok())
+        .filter_map(|(_, value)| serde_json::from_slice::<StoredStatus>(&value).ok())
+        .filter(|stored| !stored.disposed)
+        .map(|stored| stored.status.sensor_type)
+        .collect();
+    let mut reconciler = Reconciler {
+        sink,
+        present,
+        last_sent: HashMap::new(),
+        min_resend_interval: Duration::from_secs(config.min_resend_interval_secs),
+    };
+    if config.republish_on_startup {
+        reconciler.republish_all().await;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    // the first tick completes immediately, right after the startup republish
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = interval.tick() => reconciler.check_drift().await,
+            event = status_events.recv() => match event {
+                Ok(event) => reconciler.on_status_event(event).await,
+                // missed events are caught by the next drift check
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorCode};
+use crate::extract::{Json, Path};
+use crate::validation;
+use crate::AppState;
+
+/// Sensor registered by an operator, stored in the `registry` sled tree keyed by `sensor_type`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct SensorRegistration {
+    pub(crate) sensor_type: String,
+    pub(crate) name: String,
+    pub(crate) description: String,
+}
+
+/// Mutable part of a registration. Fields left out are kept as they are.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct SensorRegistrationUpdate {
+    pub(crate) name: Option<String>,
+    pub(crate) description: Option<String>,
+}
+
+pub(crate) fn load_registrations(db_tree: &sled::Tree) -> sled::Result<Vec<SensorRegistration>> {
+    let mut registrations = vec![];
+    for item in db_tree.iter() {
+        let (_, value) = item?;
+        if let Ok(registration) = serde_json::from_slice::<SensorRegistration>(&value) {
+            registrations.push(registration);
+        }
+    }
+    Ok(registrations)
+}
+
+fn decode_registration(sensor_type: &str, value: &[u8]) -> Result<SensorRegistration, ApiError> {
+    serde_json::from_slice(value).map_err(|e| {
+        ApiError::new(
+            ErrorCode::StorageFailed,
+            format!("stored registration of {sensor_type} cannot be decoded: {e}"),
+        )
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/sensor/registry",
+    request_body = SensorRegistration,
+    responses(
+        (status = 201, body = SensorRegistration, description = "Register sensor"),
+        (status = 400, body = Problem, content_type = "application/problem+json", description = "Empty or reserved sensor_type"),
+        (status = 409, body = Problem, content_type = "application/problem+json", description = "Sensor already registered"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "post_handler_sensor_registry"
+)]
+pub(crate) async fn post_handler_sensor_registry(
+    State(db): State<AppState>,
+    Json(payload): Json<SensorRegistration>,
+) -> Result<(StatusCode, Json<SensorRegistration>), ApiError> {
+    if payload.sensor_type.is_empty() {
+        return Err(ApiError::bad_request("sensor_type must not be empty"));
+    }
+    if validation::is_reserved(&payload.sensor_type) {
+        return Err(ApiError::bad_request(format!(
+            "sensor_type \"{}\" is reserved for a route",
+            payload.sensor_type
+        )));
+    }
+    let json = serde_json::to_vec(&payload).expect("SensorRegistration serializes");
+    // only insert when the key does not exist yet
+    match db.registry.compare_and_swap(
+        &payload.sensor_type,
+        None as Option<&[u8]>,
+        Some(json),
+    )? {
+        Ok(()) => Ok((StatusCode::CREATED, Json(payload))),
+        Err(_) => Err(ApiError::new(
+            ErrorCode::Conflict,
+            format!("sensor {} is already registered", payload.sensor_type),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/registry/{sensor_type}",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)")
+    ),
+    responses(
+        (status = 200, body = SensorRegistration, description = "Describe registered sensor"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Sensor not registered"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "get_handler_sensor_registry"
+)]
+pub(crate) async fn get_handler_sensor_registry(
+    State(db): State<AppState>,
+    Path(sensor_type): Path<String>,
+) -> Result<(StatusCode, Json<SensorRegistration>), ApiError> {
+    match db.registry.get(&sensor_type)? {
+        Some(value) => Ok((StatusCode::OK, Json(decode_registration(&sensor_type, &value)?))),
+        None => Err(ApiError::not_found(format!("sensor {sensor_type} is not registered"))),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/sensor/registry/{sensor_type}",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)")
+    ),
+    request_body = SensorRegistrationUpdate,
+    responses(
+        (status = 200, body = SensorRegistration, description = "Rename or describe registered sensor"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Sensor not registered"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "patch_handler_sensor_registry"
+)]
+pub(crate) async fn patch_handler_sensor_registry(
+    State(db): State<AppState>,
+    Path(sensor_type): Path<String>,
+    Json(payload): Json<SensorRegistrationUpdate>,
+) -> Result<(StatusCode, Json<SensorRegistration>), ApiError> {
+    let updated = db.registry.update_and_fetch(&sensor_type, |old| {
+        let old = old?;
+        // keep a record we cannot decode untouched instead of dropping it
+        let Ok(mut registration) = serde_json::from_slice::<SensorRegistration>(old) else {
+            return Some(old.to_vec());
+        };
+        if let Some(name) = &payload.name {
+            registration.name = name.clone();
+        }
+        if let Some(description) = &payload.description {
+            registration.description = description.clone();
+        }
+        Some(serde_json::to_vec(&registration).unwrap_or_else(|_| old.to_vec()))
+    })?;
+    match updated {
+        Some(value) => Ok((StatusCode::OK, Json(decode_registration(&sensor_type, &value)?))),
+        None => Err(ApiError::not_found(format!("sensor {sensor_type} is not registered"))),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/sensor/registry/{sensor_type}",
+    params(
+        ("sensor_type" = String, Path, description = "Sensor type (instance key)")
+    ),
+    responses(
+        (status = 204, description = "Unregister sensor"),
+        (status = 404, body = Problem, content_type = "application/problem+json", description = "Sensor not registered"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Storage failed")
+    ),
+    tag = "delete_handler_sensor_registry"
+)]
+pub(crate) async fn delete_handler_sensor_registry(
+    State(db): State<AppState>,
+    Path(sensor_type): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    match db.registry.remove(&sensor_type)? {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(ApiError::not_found(format!("sensor {sensor_type} is not registered"))),
+    }
+}
@@ -0,0 +1,448 @@
+use serde::{Deserialize, Serialize};
+use sled::transaction::{TransactionError, TransactionResult, TransactionalTree, UnabortableTransactionError};
+use sled::Transactional;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use crate::config::RetentionConfig;
+use crate::history::{self, HistoryEntry};
+
+const MINUTE_MS: u64 = 60 * 1000;
+const HOUR_MS: u64 = 60 * MINUTE_MS;
+
+/// Length of the receive time and sequence number at the end of a history key.
+const HISTORY_KEY_SUFFIX_LEN: usize = 16;
+
+/// Raw samples removed in one transaction, and aggregates in one batch.
+const REMOVE_CHUNK: usize = 256;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Resolution {
+    #[default]
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    pub(crate) fn bucket_ms(self) -> u64 {
+        match self {
+            Resolution::Minute => MINUTE_MS,
+            Resolution::Hour => HOUR_MS,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, ToSchema)]
+pub(crate) struct FieldStats {
+    pub(crate) min: u32,
+    pub(crate) max: u32,
+    pub(crate) mean: f64,
+}
+
+impl FieldStats {
+    fn add(&mut self, count: u64, value: u32) {
+        if count == 1 {
+            *self = FieldStats {
+                min: value,
+                max: value,
+                mean: value as f64,
+            };
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            self.mean += (value as f64 - self.mean) / count as f64;
+        }
+    }
+}
+
+/// Summary of the raw samples of one sensor received within one bucket.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct StatusAggregate {
+    pub(crate) sensor_type: String,
+    pub(crate) resolution: Resolution,
+    /// Bucket start in milliseconds since the Unix epoch
+    pub(crate) bucket_start: u64,
+    pub(crate) count: u64,
+    pub(crate) frequency: FieldStats,
+    pub(crate) power: FieldStats,
+    pub(crate) squelti: FieldStats,
+}
+
+impl StatusAggregate {
+    fn add(&mut self, entry: &HistoryEntry) {
+        self.count += 1;
+        self.frequency.add(self.count, entry.status.frequency);
+        self.power.add(self.count, entry.status.power);
+        self.squelti.add(self.count, entry.status.squelti);
+    }
+}
+
+/// Trees touched by the retention task.
+#[derive(Clone)]
+pub(crate) struct RetentionTrees {
+    pub(crate) history: sled::Tree,
+    pub(crate) minute: sled::Tree,
+    pub(crate) hour: sled::Tree,
+}
+
+/// Aggregates use the same key layout as the history, with the bucket start as time.
+pub(crate) fn aggregate_key(sensor_type: &str, bucket_start: u64) -> Vec<u8> {
+    let mut key = history::history_prefix(sensor_type);
+    key.extend_from_slice(&bucket_start.to_be_bytes());
+    key
+}
+
+fn fold(
+    tree: &TransactionalTree,
+    resolution: Resolution,
+    entry: &HistoryEntry,
+) -> Result<(), UnabortableTransactionError> {
+    let bucket_start = entry.received_at - entry.received_at % resolution.bucket_ms();
+    let key = aggregate_key(&entry.status.sensor_type, bucket_start);
+    let mut aggregate = tree
+        .get(&key)?
+        .and_then(|old| serde_json::from_slice::<StatusAggregate>(&old).ok())
+        .unwrap_or_else(|| StatusAggregate {
+            sensor_type: entry.status.sensor_type.clone(),
+            resolution,
+            bucket_start,
+            ..Default::default()
+        });
+    aggregate.add(entry);
+    tree.insert(key, serde_json::to_vec(&aggregate).expect("StatusAggregate always serializes"))?;
+    Ok(())
+}
+
+/// Removes raw samples and, when downsampling is on, folds them into the aggregates in the
+/// same transaction, so that a sample is never lost nor counted twice.
+fn remove_samples(trees: &RetentionTrees, config: &RetentionConfig, keys: &[sled::IVec]) -> sled::Result<usize> {
+    if keys.is_empty() {
+        return Ok(0);
+    }
+    let result: TransactionResult<usize, ()> = (&trees.history, &trees.minute, &trees.hour)
+        .transaction(|(history, minute, hour)| {
+            let mut removed = 0;
+            for key in keys {
+                let Some(value) = history.remove(key)? else {
+                    continue;
+                };
+                removed += 1;
+                if config.downsample {
+                    if let Ok(entry) = serde_json::from_slice::<HistoryEntry>(&value) {
+                        fold(minute, Resolution::Minute, &entry)?;
+                        fold(hour, Resolution::Hour, &entry)?;
+                    }
+                }
+            }
+            Ok(removed)
+        });
+    result.map_err(|e| match e {
+        TransactionError::Storage(e) => e,
+        TransactionError::Abort(()) => unreachable!("the transaction never aborts"),
+    })
+}
+
+fn expire_aggregates(tree: &sled::Tree, max_age_secs: Option<u64>, now: u64) -> sled::Result<usize> {
+    let Some(max_age_secs) = max_age_secs else {
+        return Ok(0);
+    };
+    let cutoff = now.saturating_sub(max_age_secs * 1000);
+    let mut removed = 0;
+    let mut batch = sled::Batch::default();
+    for item in tree.iter() {
+        let (key, value) = item?;
+        match serde_json::from_slice::<StatusAggregate>(&value) {
+            Ok(aggregate) if aggregate.bucket_start + aggregate.resolution.bucket_ms() > cutoff => {}
+            _ => {
+                batch.remove(key);
+                removed += 1;
+                if removed % REMOVE_CHUNK == 0 {
+                    tree.apply_batch(std::mem::take(&mut batch))?;
+                }
+            }
+        }
+    }
+    tree.apply_batch(batch)?;
+    Ok(removed)
+}
+
+fn received_at(key: &[u8]) -> Option<u64> {
+    let suffix = key.len().checked_sub(HISTORY_KEY_SUFFIX_LEN).map(|start| &key[start..])?;
+    Some(u64::from_be_bytes(suffix[..8].try_into().ok()?))
+}
+
+/// History prefixes of the sensors with samples. Each one is found by seeking past the
+/// range of the previous sensor, so the samples themselves are not read.
+fn sensor_prefixes(history: &sled::Tree) -> sled::Result<Vec<Vec<u8>>> {
+    let mut prefixes = vec![];
+    let mut start = vec![];
+    while let Some((key, _)) = history.range::<&[u8], _>(start.as_slice()..).next().transpose()? {
+        match key.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                let prefix = key[..=end].to_vec();
+                // sensor types hold no NUL, so this is the first key after the sensor
+                start = key[..=end].to_vec();
+                start[end] = 1;
+                prefixes.push(prefix);
+            }
+            None => {
+                start = key.to_vec();
+                start.push(0);
+            }
+        }
+    }
+    Ok(prefixes)
+}
+
+/// Removes the samples of one sensor beyond `max_samples_per_sensor` or older than the cutoff.
+/// Both are the oldest samples, at the start of the sensor range.
+fn enforce_sensor(
+    trees: &RetentionTrees,
+    config: &RetentionConfig,
+    prefix: &[u8],
+    age_cutoff: Option<u64>,
+) -> sled::Result<usize> {
+    let excess = match config.max_samples_per_sensor {
+        Some(max) => trees.history.scan_prefix(prefix).count().saturating_sub(max),
+        None => 0,
+    };
+    let mut removed = 0;
+    let mut keys = vec![];
+    for (index, item) in trees.history.scan_prefix(prefix).enumerate() {
+        let (key, _) = item?;
+        let too_old = age_cutoff.is_some_and(|cutoff| received_at(&key).is_some_and(|at| at < cutoff));
+        if index >= excess && !too_old {
+            break;
+        }
+        keys.push(key);
+        if keys.len() == REMOVE_CHUNK {
+            removed += remove_samples(trees, config, &keys)?;
+            keys.clear();
+        }
+    }
+    removed += remove_samples(trees, config, &keys)?;
+    Ok(removed)
+}
+
+/// Removes the oldest samples of all sensors until the raw history is at most `max_bytes`,
+/// merging the time ordered ranges of the sensors.
+fn enforce_size(trees: &RetentionTrees, config: &RetentionConfig, max_bytes: u64) -> sled::Result<usize> {
+    let mut total = 0;
+    for item in trees.history.iter() {
+        let (key, value) = item?;
+        total += (key.len() + value.len()) as u64;
+    }
+    if total <= max_bytes {
+        return Ok(0);
+    }
+
+    let mut ranges = sensor_prefixes(&trees.history)?
+        .into_iter()
+        .map(|prefix| trees.history.scan_prefix(prefix))
+        .collect::<Vec<_>>();
+    let mut heads = ranges
+        .iter_mut()
+        .map(|range| range.next().transpose())
+        .collect::<sled::Result<Vec<_>>>()?;
+    let mut removed = 0;
+    let mut keys = vec![];
+    while total > max_bytes {
+        let oldest = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(index, head)| head.as_ref().map(|(key, _)| (received_at(key), index)))
+            .min();
+        let Some((_, index)) = oldest else {
+            break;
+        };
+        let (key, value) = heads[index].take().expect("picked above");
+        heads[index] = ranges[index].next().transpose()?;
+        total = total.saturating_sub((key.len() + value.len()) as u64);
+        keys.push(key);
+        if keys.len() == REMOVE_CHUNK {
+            removed += remove_samples(trees, config, &keys)?;
+            keys.clear();
+        }
+    }
+    removed += remove_samples(trees, config, &keys)?;
+    Ok(removed)
+}
+
+/// Runs one enforcement pass and returns the number of removed raw samples.
+pub(crate) fn enforce(trees: &RetentionTrees, config: &RetentionConfig) -> sled::Result<usize> {
+    let now = history::now_millis();
+    let age_cutoff = config
+        .max_age_secs
+        .map(|max_age_secs| now.saturating_sub(max_age_secs * 1000));
+
+    let mut removed = 0;
+    if age_cutoff.is_some() || config.max_samples_per_sensor.is_some() {
+        for prefix in sensor_prefixes(&trees.history)? {
+            removed += enforce_sensor(trees, config, &prefix, age_cutoff)?;
+        }
+    }
+    if let Some(max_bytes) = config.max_bytes {
+        removed += enforce_size(trees, config, max_bytes)?;
+    }
+    expire_aggregates(&trees.minute, config.minute_aggregate_max_age_secs, now)?;
+    expire_aggregates(&trees.hour, config.hour_aggregate_max_age_secs, now)?;
+    Ok(removed)
+}
+
+/// Background task enforcing the retention limits every `interval_secs`.
+pub(crate) async fn run_retention(trees: RetentionTrees, config: RetentionConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    loop {
+        interval.tick().await;
+        let trees = trees.clone();
+        let config = config.clone();
+        match tokio::task::spawn_blocking(move || enforce(&trees, &config)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => println!("retention: removed {} samples", removed),
+            Ok(Err(e)) => println!("retention: {:?}", e),
+            Err(e) => println!("retention: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SensorStatus;
+
+    fn trees() -> RetentionTrees {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        RetentionTrees {
+            history: db.open_tree("history").unwrap(),
+            minute: db.open_tree("history_minute").unwrap(),
+            hour: db.open_tree("history_hour").unwrap(),
+        }
+    }
+
+    fn insert(trees: &RetentionTrees, sensor_type: &str, received_at: u64, frequency: u32) {
+        let status = SensorStatus {
+            sensor_type: sensor_type.to_string(),
+            frequency,
+            power: 10,
+            squelti: 1,
+        };
+        history::append_history(&trees.history, received_at, received_at, &status).unwrap();
+    }
+
+    fn times(trees: &RetentionTrees, sensor_type: &str) -> Vec<u64> {
+        trees
+            .history
+            .scan_prefix(history::history_prefix(sensor_type))
+            .map(|item| received_at(&item.unwrap().0).unwrap())
+            .collect()
+    }
+
+    fn aggregate(tree: &sled::Tree, sensor_type: &str, bucket_start: u64) -> Option<StatusAggregate> {
+        tree.get(aggregate_key(sensor_type, bucket_start))
+            .unwrap()
+            .map(|value| serde_json::from_slice(&value).unwrap())
+    }
+
+    fn config() -> RetentionConfig {
+        RetentionConfig {
+            minute_aggregate_max_age_secs: None,
+            ..RetentionConfig::default()
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_samples_of_each_sensor() {
+        let trees = trees();
+        // "a" is a prefix of "ab", their ranges must stay apart
+        for i in 0..5 {
+            insert(&trees, "a", HOUR_MS + i * 1000, 2400 + i as u32);
+            insert(&trees, "ab", HOUR_MS + i * 1000, 5200);
+        }
+        let config = RetentionConfig {
+            max_samples_per_sensor: Some(2),
+            ..config()
+        };
+        assert_eq!(enforce(&trees, &config).unwrap(), 6);
+        assert_eq!(times(&trees, "a"), vec![HOUR_MS + 3000, HOUR_MS + 4000]);
+        assert_eq!(times(&trees, "ab"), vec![HOUR_MS + 3000, HOUR_MS + 4000]);
+
+        let minute = aggregate(&trees.minute, "a", HOUR_MS).unwrap();
+        assert_eq!(minute.count, 3);
+        assert_eq!((minute.frequency.min, minute.frequency.max), (2400, 2402));
+        assert_eq!(minute.frequency.mean, 2401.0);
+        assert_eq!(aggregate(&trees.hour, "ab", HOUR_MS).unwrap().count, 3);
+        assert_eq!(enforce(&trees, &config).unwrap(), 0);
+    }
+
+    #[test]
+    fn removes_samples_older_than_max_age() {
+        let trees = trees();
+        let now = history::now_millis();
+        for age in [3 * HOUR_MS, 2 * HOUR_MS, 1000] {
+            insert(&trees, "radar", now - age, 2450);
+        }
+        let config = RetentionConfig {
+            max_age_secs: Some(3600),
+            ..config()
+        };
+        assert_eq!(enforce(&trees, &config).unwrap(), 2);
+        assert_eq!(times(&trees, "radar"), vec![now - 1000]);
+    }
+
+    #[test]
+    fn size_limit_removes_the_oldest_samples_of_all_sensors() {
+        let trees = trees();
+        for i in 0..3 {
+            insert(&trees, "a", HOUR_MS + 2 * i, 2450);
+            insert(&trees, "b", HOUR_MS + 2 * i + 1, 2450);
+        }
+        let (key, value) = trees.history.first().unwrap().unwrap();
+        let sample_size = (key.len() + value.len()) as u64;
+        let config = RetentionConfig {
+            max_bytes: Some(3 * sample_size),
+            ..config()
+        };
+        assert_eq!(enforce(&trees, &config).unwrap(), 3);
+        assert_eq!(times(&trees, "a"), vec![HOUR_MS + 4]);
+        assert_eq!(times(&trees, "b"), vec![HOUR_MS + 3, HOUR_MS + 5]);
+        assert_eq!(aggregate(&trees.minute, "a", HOUR_MS).unwrap().count, 2);
+        assert_eq!(aggregate(&trees.minute, "b", HOUR_MS).unwrap().count, 1);
+    }
+
+    #[test]
+    fn removed_samples_are_not_folded_without_downsampling() {
+        let trees = trees();
+        for i in 0..3 {
+            insert(&trees, "radar", HOUR_MS + i, 2450);
+        }
+        let config = RetentionConfig {
+            max_samples_per_sensor: Some(1),
+            downsample: false,
+            ..config()
+        };
+        assert_eq!(enforce(&trees, &config).unwrap(), 2);
+        assert!(trees.minute.is_empty());
+        assert!(trees.hour.is_empty());
+    }
+
+    #[test]
+    fn expired_aggregates_are_removed() {
+        let trees = trees();
+        let now = history::now_millis();
+        let old = now - 10 * MINUTE_MS;
+        let recent = now - now % MINUTE_MS;
+        insert(&trees, "radar", old, 2450);
+        insert(&trees, "radar", recent, 2450);
+        let config = RetentionConfig {
+            max_samples_per_sensor: Some(0),
+            minute_aggregate_max_age_secs: Some(5 * 60),
+            ..RetentionConfig::default()
+        };
+        assert_eq!(enforce(&trees, &config).unwrap(), 2);
+        assert_eq!(trees.minute.len(), 1);
+        assert!(aggregate(&trees.minute, "radar", recent).is_some());
+        // hour aggregates are kept without hour_aggregate_max_age_secs
+        assert!(!trees.hour.is_empty());
+    }
+}
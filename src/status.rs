@@ -0,0 +1,190 @@
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use utoipa::IntoParams;
+
+use crate::extract::Query;
+use crate::history;
+use crate::{AppState, SensorStatus};
+
+/// Capacity of the channel fanning received samples out to stream clients.
+pub(crate) const STATUS_EVENT_CAPACITY: usize = 256;
+
+/// Value stored in the `status` sled tree. `seq` is the id of the sample that last updated
+/// the record and is used as the SSE event id. A disposed instance keeps its last status.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct StoredStatus {
+    #[serde(default)]
+    pub(crate) seq: u64,
+    #[serde(default)]
+    pub(crate) disposed: bool,
+    #[serde(flatten)]
+    pub(crate) status: SensorStatus,
+}
+
+/// SensorStatus sample or dispose as received by the background subscriber.
+#[derive(Clone, Debug)]
+pub(crate) struct StatusEvent {
+    pub(crate) seq: u64,
+    pub(crate) disposed: bool,
+    pub(crate) status: SensorStatus,
+}
+
+impl From<StoredStatus> for StatusEvent {
+    fn from(stored: StoredStatus) -> Self {
+        StatusEvent {
+            seq: stored.seq,
+            disposed: stored.disposed,
+            status: stored.status,
+        }
+    }
+}
+
+impl StatusEvent {
+    fn to_sse(&self) -> Event {
+        Event::default()
+            .event(if self.disposed { "dispose" } else { "status" })
+            .id(self.seq.to_string())
+            .json_data(&self.status)
+            .unwrap_or_else(|_| Event::default().event("error").id(self.seq.to_string()))
+    }
+}
+
+/// Stores a received sample with a new sequence number as the latest status of its sensor,
+/// appends it to the history and returns the matching event.
+pub(crate) fn store_status(
+    db: &sled::Db,
+    db_status: &sled::Tree,
+    db_history: &sled::Tree,
+    status: SensorStatus,
+) -> sled::Result<StatusEvent> {
+    let stored = StoredStatus {
+        seq: db.generate_id()?,
+        disposed: false,
+        status,
+    };
+    history::append_history(db_history, history::now_millis(), stored.seq, &stored.status)?;
+    let json = serde_json::to_vec(&stored).expect("SensorStatus always serializes");
+    db_status.insert(&stored.status.sensor_type, json)?;
+    Ok(stored.into())
+}
+
+/// Marks the stored status of a disposed instance, keeping its last reported values.
+pub(crate) fn store_dispose(
+    db: &sled::Db,
+    db_status: &sled::Tree,
+    sensor_type: String,
+) -> sled::Result<StatusEvent> {
+    let status = match db_status.get(&sensor_type)? {
+        Some(value) => serde_json::from_slice::<StoredStatus>(&value)
+            .map(|stored| stored.status)
+            .unwrap_or_default(),
+        None => SensorStatus::default(),
+    };
+    let stored = StoredStatus {
+        seq: db.generate_id()?,
+        disposed: true,
+        status: SensorStatus {
+            sensor_type,
+            ..status
+        },
+    };
+    let json = serde_json::to_vec(&stored).expect("SensorStatus always serializes");
+    db_status.insert(&stored.status.sensor_type, json)?;
+    Ok(stored.into())
+}
+
+/// Reads the stored status of a sensor. Disposed instances are returned as well.
+pub(crate) fn load_status(db_status: &sled::Tree, sensor_type: &str) -> sled::Result<Option<StoredStatus>> {
+    Ok(db_status
+        .get(sensor_type)?
+        .and_then(|value| serde_json::from_slice::<StoredStatus>(&value).ok()))
+}
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub(crate) struct StatusStreamQuery {
+    /// Comma separated sensor types to receive. All sensors when left out.
+    sensor_type: Option<String>,
+}
+
+impl StatusStreamQuery {
+    fn filter(&self) -> Option<Vec<String>> {
+        self.sensor_type.as_ref().map(|sensor_types| {
+            sensor_types
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+    }
+}
+
+fn matches(filter: &Option<Vec<String>>, status: &SensorStatus) -> bool {
+    match filter {
+        Some(sensor_types) => sensor_types.contains(&status.sensor_type),
+        None => true,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/status/stream",
+    params(
+        StatusStreamQuery,
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event id")
+    ),
+    responses(
+        (status = 200, content_type = "text/event-stream", description = "Stream of SensorStatus samples as `status` events and disposed instances as `dispose` events"),
+    ),
+    tag = "get_handler_sensor_status_stream"
+)]
+pub(crate) async fn get_handler_sensor_status_stream(
+    State(state): State<AppState>,
+    Query(query): Query<StatusStreamQuery>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let filter = query.filter();
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    // subscribe before reading sled so that no sample is lost in between
+    let receiver = state.status_events.subscribe();
+
+    // on resume, send the latest status of every sensor that changed since the given id
+    let mut replay: Vec<StatusEvent> = vec![];
+    if let Some(last_event_id) = last_event_id {
+        for item in state.status.iter() {
+            let Ok((_, value)) = item else {
+                continue;
+            };
+            if let Ok(stored) = serde_json::from_slice::<StoredStatus>(&value) {
+                if stored.seq > last_event_id && matches(&filter, &stored.status) {
+                    replay.push(stored.into());
+                }
+            }
+        }
+        replay.sort_by_key(|event| event.seq);
+    }
+    let replayed_up_to = replay.last().map(|event| event.seq).or(last_event_id);
+
+    let replay = tokio_stream::iter(replay).map(|event| Ok(event.to_sse()));
+    // lagging clients skip the samples they missed; they can resume with Last-Event-ID
+    let live = BroadcastStream::new(receiver).filter_map(move |event| match event {
+        Ok(event)
+            if matches(&filter, &event.status)
+                && replayed_up_to.is_none_or(|seq| event.seq > seq) =>
+        {
+            Some(Ok(event.to_sse()))
+        }
+        _ => None,
+    });
+
+    Sse::new(replay.chain(live)).keep_alive(KeepAlive::default())
+}
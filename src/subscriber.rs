@@ -0,0 +1,175 @@
+use axum::{extract::State, http::StatusCode, Json};
+use rustdds::with_key::Sample;
+use rustdds::{QosPolicies, RTPSEntity, Subscriber, Topic};
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use crate::config::TopicConfig;
+use crate::dds::QosRegistry;
+use crate::discovery::Discovery;
+use crate::error::{ApiError, ErrorCode};
+use crate::history;
+use crate::metrics::Metrics;
+use crate::status::{self, StatusEvent};
+use crate::{AppState, SensorStatus};
+
+pub(crate) const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+pub(crate) const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Counters of the background subscriber, served by `GET /sensor/ingestion`.
+#[derive(Serialize, Clone, Debug, Default, ToSchema)]
+pub(crate) struct IngestionState {
+    /// Whether a DataReader is currently attached to the SensorStatus topic
+    pub(crate) running: bool,
+    pub(crate) samples_received: u64,
+    pub(crate) disposes_received: u64,
+    /// Samples that could not be read, e.g. because they failed to deserialize
+    pub(crate) read_errors: u64,
+    pub(crate) store_errors: u64,
+    /// Number of times the DataReader was recreated after its stream ended
+    pub(crate) restarts: u64,
+    /// Receive time of the last sample in milliseconds since the Unix epoch
+    pub(crate) last_sample_at: Option<u64>,
+    pub(crate) last_error: Option<String>,
+}
+
+pub(crate) type IngestionStateHandle = Arc<RwLock<IngestionState>>;
+
+/// Everything the background subscriber writes to.
+pub(crate) struct StatusSink {
+    pub(crate) db: sled::Db,
+    pub(crate) status: sled::Tree,
+    pub(crate) history: sled::Tree,
+    pub(crate) status_events: broadcast::Sender<StatusEvent>,
+    pub(crate) ingestion: IngestionStateHandle,
+    pub(crate) qos_registry: QosRegistry,
+    pub(crate) discovery: Discovery,
+    pub(crate) metrics: Metrics,
+}
+
+impl StatusSink {
+    fn update(&self, f: impl FnOnce(&mut IngestionState)) {
+        if let Ok(mut ingestion) = self.ingestion.write() {
+            f(&mut ingestion);
+        }
+    }
+
+    fn handle(&self, topic: &str, sample: Sample<SensorStatus, String>) {
+        let stored = match sample {
+            Sample::Value(status) => {
+                self.metrics.record_received(topic, &status.sensor_type);
+                self.update(|s| {
+                    s.samples_received += 1;
+                    s.last_sample_at = Some(history::now_millis());
+                });
+                status::store_status(&self.db, &self.status, &self.history, status)
+            }
+            Sample::Dispose(sensor_type) => {
+                self.update(|s| {
+                    s.disposes_received += 1;
+                    s.last_sample_at = Some(history::now_millis());
+                });
+                status::store_dispose(&self.db, &self.status, sensor_type)
+            }
+        };
+        match stored {
+            Ok(event) => {
+                println!("subscribe: {:?}", event);
+                // no receiver just means no stream client is connected
+                let _ = self.status_events.send(event);
+            }
+            Err(e) => {
+                eprintln!("subscriber store error: {:?}", e);
+                self.update(|s| {
+                    s.store_errors += 1;
+                    s.last_error = Some(e.to_string());
+                });
+            }
+        }
+    }
+}
+
+/// Reads SensorStatus samples into sled. The DataReader is recreated with a backoff
+/// whenever its stream ends or it cannot be created.
+pub(crate) async fn run_subscriber(
+    subscriber: Subscriber,
+    topic: Topic,
+    qos: QosPolicies,
+    topic_config: TopicConfig,
+    sink: StatusSink,
+) {
+    let entity_name = format!("datareader/{}", topic_config.name);
+    let mut backoff = RESTART_BACKOFF_MIN;
+    let mut first = true;
+    loop {
+        if !first {
+            sink.update(|s| s.restarts += 1);
+            sink.metrics.record_subscriber_restart();
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+        }
+        first = false;
+
+        let reader = match subscriber.create_datareader_cdr::<SensorStatus>(&topic, Some(qos.clone())) {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!("subscriber create error: {:?}", e);
+                sink.update(|s| s.last_error = Some(e.to_string()));
+                continue;
+            }
+        };
+        sink.qos_registry.record(
+            &entity_name,
+            "datareader",
+            Some(&topic_config.name),
+            Some(&topic_config.qos_profile),
+            &reader,
+        );
+        sink.discovery.register(&entity_name, reader.guid());
+        let mut async_reader = reader.async_sample_stream();
+        sink.update(|s| s.running = true);
+        println!("subscriber start");
+
+        while let Some(result) = async_reader.next().await {
+            match result {
+                Ok(sample) => {
+                    sink.handle(&topic_config.name, sample);
+                    backoff = RESTART_BACKOFF_MIN;
+                }
+                Err(e) => {
+                    eprintln!("subscriber read error: {:?}", e);
+                    sink.update(|s| {
+                        s.read_errors += 1;
+                        s.last_error = Some(e.to_string());
+                    });
+                }
+            }
+        }
+
+        eprintln!("subscriber stream ended, recreating reader");
+        sink.qos_registry.remove(&entity_name);
+        sink.update(|s| s.running = false);
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/ingestion",
+    responses(
+        (status = 200, body = IngestionState, description = "Get state of the SensorStatus subscriber"),
+        (status = 500, body = Problem, content_type = "application/problem+json", description = "Internal error")
+    ),
+    tag = "get_handler_sensor_ingestion"
+)]
+pub(crate) async fn get_handler_sensor_ingestion(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<IngestionState>), ApiError> {
+    match state.ingestion.read() {
+        Ok(ingestion) => Ok((StatusCode::OK, Json(ingestion.clone()))),
+        Err(_) => Err(ApiError::new(ErrorCode::Internal, "ingestion state lock is poisoned")),
+    }
+}
@@ -0,0 +1,266 @@
+use axum::{extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::{ValidationConfig, ValueRange};
+use crate::error::ApiError;
+use crate::extract::Json;
+use crate::{AppState, SensorConfig};
+
+/// Longest sensor_type accepted, it is used as instance key and sled key.
+const MAX_SENSOR_TYPE_LEN: usize = 256;
+
+/// Sensor types that match a static route next to `/sensor/config/{sensor_type}` or
+/// `/sensor/status/{sensor_type}`, such a sensor could not be reached through the parameterized
+/// routes.
+const RESERVED_SENSOR_TYPES: &[&str] = &["stream", "validate", "batch", "queue"];
+
+pub(crate) fn is_reserved(sensor_type: &str) -> bool {
+    RESERVED_SENSOR_TYPES.contains(&sensor_type)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct FieldError {
+    pub(crate) field: String,
+    pub(crate) message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub(crate) struct ValidationReport {
+    pub(crate) valid: bool,
+    pub(crate) errors: Vec<FieldError>,
+}
+
+impl ValidationReport {
+    /// Turns a failed validation into a 422 problem.
+    pub(crate) fn into_result(self) -> Result<ValidationReport, ApiError> {
+        if self.valid {
+            Ok(self)
+        } else {
+            Err(ApiError::validation(self.describe(), self.errors))
+        }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        self.errors
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+fn describe_ranges(ranges: &[ValueRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| format!("{}..={}", range.min, range.max))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Checks a config against the capability profile of its sensor type.
+pub(crate) fn validate_config(rules: &ValidationConfig, config: &SensorConfig) -> ValidationReport {
+    let mut errors = vec![];
+    let mut error = |field: &str, message: String| {
+        errors.push(FieldError {
+            field: field.to_string(),
+            message,
+        })
+    };
+
+    let sensor_type = &config.sensor_type;
+    if sensor_type.trim().is_empty() {
+        error("sensor_type", String::from("must not be empty"));
+    } else if sensor_type.trim() != sensor_type {
+        error("sensor_type", String::from("must not start or end with whitespace"));
+    } else if sensor_type.len() > MAX_SENSOR_TYPE_LEN {
+        error(
+            "sensor_type",
+            format!("must be at most {MAX_SENSOR_TYPE_LEN} bytes"),
+        );
+    } else if is_reserved(sensor_type) {
+        error(
+            "sensor_type",
+            format!("\"{sensor_type}\" is reserved for a route"),
+        );
+    }
+
+    match rules.capabilities.get(sensor_type) {
+        Some(profile) => {
+            if !profile.frequency_bands.is_empty()
+                && !profile
+                    .frequency_bands
+                    .iter()
+                    .any(|band| band.contains(config.frequency))
+            {
+                error(
+                    "frequency",
+                    format!(
+                        "{} is outside the allowed bands {}",
+                        config.frequency,
+                        describe_ranges(&profile.frequency_bands)
+                    ),
+                );
+            }
+            if let Some(max_power) = profile.max_power {
+                if config.power > max_power {
+                    error(
+                        "power",
+                        format!("{} exceeds the maximum of {max_power}", config.power),
+                    );
+                }
+            }
+            if let Some(squelch) = &profile.squelch {
+                if !squelch.contains(config.squelti) {
+                    error(
+                        "squelti",
+                        format!(
+                            "{} is outside the squelch range {}",
+                            config.squelti,
+                            describe_ranges(std::slice::from_ref(squelch))
+                        ),
+                    );
+                }
+            }
+        }
+        None if rules.require_profile && !sensor_type.trim().is_empty() => error(
+            "sensor_type",
+            format!("no capability profile for sensor type \"{sensor_type}\""),
+        ),
+        None => {}
+    }
+
+    ValidationReport {
+        valid: errors.is_empty(),
+        errors,
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/sensor/config/validate",
+    request_body = SensorConfig,
+    responses(
+        (status = 200, body = ValidationReport, description = "Config passes validation, nothing is published"),
+        (status = 422, body = Problem, content_type = "application/problem+json", description = "Config violates the capability profile of the sensor type")
+    ),
+    tag = "post_handler_sensor_config_validate"
+)]
+pub(crate) async fn post_handler_sensor_config_validate(
+    State(state): State<AppState>,
+    Json(payload): Json<SensorConfig>,
+) -> Result<(StatusCode, Json<ValidationReport>), ApiError> {
+    let report = validate_config(&state.validation, &payload).into_result()?;
+    Ok((StatusCode::OK, Json(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(require_profile: bool) -> ValidationConfig {
+        let mut rules: ValidationConfig = toml::from_str(
+            r#"
+            [capabilities.radar]
+            frequency_bands = [{ min = 2400, max = 2500 }, { min = 5150, max = 5850 }]
+            max_power = 100
+            squelch = { min = 0, max = 10 }
+
+            [capabilities.sonar]
+            "#,
+        )
+        .unwrap();
+        rules.require_profile = require_profile;
+        rules
+    }
+
+    fn config(sensor_type: &str, frequency: u32, power: u32, squelti: u32) -> SensorConfig {
+        SensorConfig {
+            sensor_type: sensor_type.to_string(),
+            frequency,
+            power,
+            squelti,
+        }
+    }
+
+    fn fields(report: &ValidationReport) -> Vec<&str> {
+        report.errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn accepts_values_within_the_profile() {
+        for config in [
+            config("radar", 2400, 0, 0),
+            config("radar", 2500, 100, 10),
+            config("radar", 5150, 50, 5),
+            config("radar", 5850, 1, 1),
+        ] {
+            let report = validate_config(&rules(false), &config);
+            assert!(report.valid, "{config:?}: {}", report.describe());
+            assert!(report.into_result().is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_values_outside_the_profile() {
+        let rules = rules(false);
+        let report = validate_config(&rules, &config("radar", 2501, 100, 10));
+        assert_eq!(fields(&report), vec!["frequency"]);
+        assert_eq!(
+            report.errors[0].message,
+            "2501 is outside the allowed bands 2400..=2500, 5150..=5850"
+        );
+        assert_eq!(fields(&validate_config(&rules, &config("radar", 5000, 50, 5))), vec!["frequency"]);
+        assert_eq!(fields(&validate_config(&rules, &config("radar", 2450, 101, 5))), vec!["power"]);
+        assert_eq!(fields(&validate_config(&rules, &config("radar", 2450, 50, 11))), vec!["squelti"]);
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let report = validate_config(&rules(false), &config("radar", 1, 1000, 99));
+        assert!(!report.valid);
+        assert_eq!(fields(&report), vec!["frequency", "power", "squelti"]);
+        assert_eq!(
+            report.describe(),
+            "frequency: 1 is outside the allowed bands 2400..=2500, 5150..=5850; \
+             power: 1000 exceeds the maximum of 100; squelti: 99 is outside the squelch range 0..=10"
+        );
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn profile_without_limits_accepts_anything() {
+        assert!(validate_config(&rules(true), &config("sonar", u32::MAX, u32::MAX, u32::MAX)).valid);
+    }
+
+    #[test]
+    fn unknown_sensor_types_need_a_profile_only_when_required() {
+        let unknown = config("lidar", 1, 1, 1);
+        assert!(validate_config(&rules(false), &unknown).valid);
+        let report = validate_config(&rules(true), &unknown);
+        assert_eq!(fields(&report), vec!["sensor_type"]);
+        assert_eq!(report.errors[0].message, "no capability profile for sensor type \"lidar\"");
+    }
+
+    #[test]
+    fn rejects_invalid_sensor_types() {
+        let rules = rules(false);
+        for (sensor_type, message) in [
+            ("", "must not be empty"),
+            ("  ", "must not be empty"),
+            (" radar", "must not start or end with whitespace"),
+        ] {
+            let report = validate_config(&rules, &config(sensor_type, 2450, 1, 1));
+            assert_eq!(fields(&report), vec!["sensor_type"], "{sensor_type:?}");
+            assert_eq!(report.errors[0].message, message);
+        }
+        for sensor_type in RESERVED_SENSOR_TYPES {
+            let report = validate_config(&rules, &config(sensor_type, 2450, 1, 1));
+            assert_eq!(fields(&report), vec!["sensor_type"], "{sensor_type:?}");
+            assert_eq!(report.errors[0].message, format!("\"{sensor_type}\" is reserved for a route"));
+        }
+        let long = "r".repeat(MAX_SENSOR_TYPE_LEN + 1);
+        let report = validate_config(&rules, &config(&long, 2450, 1, 1));
+        assert_eq!(report.errors[0].message, "must be at most 256 bytes");
+    }
+}
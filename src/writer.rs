@@ -0,0 +1,692 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+use rustdds::with_key::DataWriter;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::config::WriterConfig;
+use crate::confirm::Acknowledgement;
+use crate::desired::{self, DesiredConfig, InstanceState};
+use crate::discovery::Discovery;
+use crate::error::{ApiError, ErrorCode};
+use crate::metrics::Metrics;
+use crate::SensorConfig;
+
+/// Response header set when a later config for the same sensor was written instead.
+pub(crate) const COALESCED_HEADER: &str = "x-coalesced";
+
+/// Commands taken from the channel at once. Repeated writes of a key are coalesced within it.
+const MAX_BATCH: usize = 64;
+
+/// DataWriter of the SensorConfig topic, owned by the writer task.
+pub(crate) struct ConfigWriter {
+    /// Shared with the blocking pool for disposes
+    writer: Arc<DataWriter<SensorConfig>>,
+    topic: String,
+    /// Whether the QoS is reliable, so that readers acknowledge samples
+    reliable: bool,
+    metrics: Metrics,
+    desired: sled::Tree,
+    /// Tracks the readers matched with the writer
+    discovery: Discovery,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WriteOptions {
+    /// Fail with `NoMatchedReaders` instead of writing when no reader is matched
+    pub(crate) require_matched: bool,
+    /// Wait this long for the matched readers to acknowledge the sample
+    pub(crate) ack: Option<Duration>,
+    /// Record the written config as the desired config of the sensor. Done by the writer task
+    /// so that the desired configs follow the order of the writes
+    pub(crate) record_desired: bool,
+    /// Allow a later write of the same sensor waiting in the queue to replace this one. Only for
+    /// callers that do not wait for this exact config to be acknowledged or applied
+    pub(crate) coalesce: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct WriteOutcome {
+    /// Remote readers matched with the writer when writing
+    pub(crate) matched_readers: usize,
+    pub(crate) acknowledgement: Option<Acknowledgement>,
+    /// Config written instead of this one when the write was coalesced
+    pub(crate) superseded_by: Option<SensorConfig>,
+}
+
+/// Outcome of writing several configs back to back.
+#[derive(Clone, Debug)]
+pub(crate) struct SetOutcome {
+    /// Matched readers of each write, in order. Writing stops at the first failure
+    pub(crate) written: Vec<Result<usize, WriteFailure>>,
+    /// Acknowledgement of the whole set, present with `ack` when every config was written
+    pub(crate) acknowledgement: Option<Acknowledgement>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum WriteFailure {
+    NoMatchedReaders,
+    /// Acknowledgments were requested but the QoS is not reliable
+    NotReliable,
+    Dds(String),
+    /// The request was not done within the writer timeout. It may still be written later
+    Timeout,
+    /// The writer task has ended
+    Stopped,
+    /// Done on DDS, but the desired config could not be stored
+    Storage(String),
+}
+
+impl fmt::Display for WriteFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteFailure::NoMatchedReaders => write!(f, "no reader is matched with the SensorConfig writer"),
+            WriteFailure::NotReliable => write!(f, "acknowledgments need a reliable QoS profile on the SensorConfig topic"),
+            WriteFailure::Dds(e) => write!(f, "{e}"),
+            WriteFailure::Timeout => write!(f, "the SensorConfig writer did not complete the request in time"),
+            WriteFailure::Stopped => write!(f, "the SensorConfig writer has stopped"),
+            WriteFailure::Storage(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<WriteFailure> for ApiError {
+    fn from(failure: WriteFailure) -> Self {
+        let code = match failure {
+            WriteFailure::NoMatchedReaders => ErrorCode::NoMatchedReaders,
+            WriteFailure::NotReliable => ErrorCode::BadRequest,
+            WriteFailure::Dds(_) => ErrorCode::DdsWriteFailed,
+            WriteFailure::Timeout => ErrorCode::Timeout,
+            WriteFailure::Stopped => ErrorCode::Internal,
+            WriteFailure::Storage(_) => ErrorCode::StorageFailed,
+        };
+        ApiError::new(code, failure.to_string())
+    }
+}
+
+type WriteReply = oneshot::Sender<Result<WriteOutcome, WriteFailure>>;
+type SetReply = oneshot::Sender<Result<SetOutcome, WriteFailure>>;
+
+enum Command {
+    Write {
+        config: SensorConfig,
+        options: WriteOptions,
+        reply: WriteReply,
+    },
+    /// Written without any other request in between
+    WriteSet {
+        configs: Vec<SensorConfig>,
+        options: WriteOptions,
+        reply: SetReply,
+    },
+    Dispose {
+        sensor_type: String,
+        reply: oneshot::Sender<Result<DesiredConfig, WriteFailure>>,
+    },
+}
+
+/// DDS side of the writer task.
+pub(crate) trait ConfigSink: Send + Sync + 'static {
+    /// Whether readers acknowledge samples
+    fn reliable(&self) -> bool;
+
+    /// Writes the config and returns the number of matched readers.
+    fn write(
+        &self,
+        config: SensorConfig,
+        options: &WriteOptions,
+    ) -> impl Future<Output = Result<usize, WriteFailure>> + Send;
+
+    /// Disposes the instance and records it as disposed in the desired configs.
+    fn dispose(&self, sensor_type: &str) -> impl Future<Output = Result<DesiredConfig, WriteFailure>> + Send;
+
+    /// Waits until the readers matched now acknowledged everything written so far.
+    fn acknowledgement(&self, timeout: Duration) -> impl Future<Output = Result<Acknowledgement, WriteFailure>> + Send;
+}
+
+impl ConfigWriter {
+    pub(crate) fn new(
+        writer: DataWriter<SensorConfig>,
+        topic: String,
+        reliable: bool,
+        metrics: Metrics,
+        desired: sled::Tree,
+        discovery: Discovery,
+    ) -> Self {
+        ConfigWriter {
+            writer: Arc::new(writer),
+            topic,
+            reliable,
+            metrics,
+            desired,
+            discovery,
+        }
+    }
+
+    fn matched_readers(&self) -> Vec<String> {
+        self.discovery.matched_guids(&format!("datawriter/{}", self.topic))
+    }
+}
+
+impl ConfigSink for ConfigWriter {
+    fn reliable(&self) -> bool {
+        self.reliable
+    }
+
+    async fn write(&self, config: SensorConfig, options: &WriteOptions) -> Result<usize, WriteFailure> {
+        if options.ack.is_some() && !self.reliable {
+            return Err(WriteFailure::NotReliable);
+        }
+        let matched_readers = self.matched_readers().len();
+        if matched_readers == 0 && options.require_matched {
+            return Err(WriteFailure::NoMatchedReaders);
+        }
+        let key = config.sensor_type.clone();
+        let desired = options.record_desired.then(|| config.clone());
+        let result = self.writer.async_write(config, None).await;
+        self.metrics.record_write(&self.topic, &key, result.is_ok());
+        result.map_err(|e| WriteFailure::Dds(format!("cannot write config of {key}: {e}")))?;
+        if let Some(config) = desired {
+            if let Err(e) = desired::record_config(&self.desired, &config) {
+                println!("cannot store desired config of {key}: {e}");
+            }
+        }
+        Ok(matched_readers)
+    }
+
+    async fn dispose(&self, sensor_type: &str) -> Result<DesiredConfig, WriteFailure> {
+        // rustdds has no async dispose, and the blocking one may wait for the history to drain
+        let writer = self.writer.clone();
+        let key = sensor_type.to_string();
+        let result = tokio::task::spawn_blocking(move || writer.dispose(&key, None))
+            .await
+            .map_err(|e| WriteFailure::Dds(format!("cannot dispose {sensor_type}: {e}")))?;
+        self.metrics.record_write(&self.topic, sensor_type, result.is_ok());
+        result.map_err(|e| WriteFailure::Dds(format!("cannot dispose {sensor_type}: {e}")))?;
+        // recorded here like written configs, so that a later write cannot be overtaken
+        desired::record_instance_state(&self.desired, sensor_type, InstanceState::Disposed)
+            .map_err(|e| WriteFailure::Storage(format!("cannot store disposal of {sensor_type}: {e}")))
+    }
+
+    /// rustdds only tells whether all readers acknowledged, so on a timeout every reader is
+    /// listed as timed out.
+    async fn acknowledgement(&self, timeout: Duration) -> Result<Acknowledgement, WriteFailure> {
+        let readers = self.matched_readers();
+        let started = Instant::now();
+        let all_acknowledged = match tokio::time::timeout(timeout, self.writer.async_wait_for_acknowledgments()).await {
+            Ok(acknowledged) => {
+                acknowledged.map_err(|e| WriteFailure::Dds(format!("cannot wait for acknowledgments: {e}")))?
+            }
+            Err(_) => false,
+        };
+        let (acknowledged, timed_out) = if all_acknowledged {
+            (readers, vec![])
+        } else {
+            (vec![], readers)
+        };
+        Ok(Acknowledgement {
+            acknowledged,
+            timed_out,
+            waited_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+/// Sends requests to the writer task.
+#[derive(Clone)]
+pub(crate) struct WriterHandle {
+    commands: mpsc::Sender<Command>,
+    timeout: Duration,
+    metrics: Metrics,
+}
+
+impl WriterHandle {
+    pub(crate) fn spawn(writer: impl ConfigSink, config: &WriterConfig, metrics: Metrics) -> Self {
+        let (commands, receiver) = mpsc::channel(config.queue_capacity);
+        tokio::spawn(run_writer(writer, receiver, metrics.clone()));
+        WriterHandle {
+            commands,
+            timeout: Duration::from_millis(config.timeout_ms),
+            metrics,
+        }
+    }
+
+    pub(crate) async fn write(&self, config: SensorConfig, options: WriteOptions) -> Result<WriteOutcome, WriteFailure> {
+        let timeout = self.timeout + options.ack.unwrap_or_default();
+        let (reply, receiver) = oneshot::channel();
+        let command = Command::Write {
+            config,
+            options,
+            reply,
+        };
+        self.request(command, receiver, timeout).await
+    }
+
+    pub(crate) async fn write_set(
+        &self,
+        configs: Vec<SensorConfig>,
+        options: WriteOptions,
+    ) -> Result<SetOutcome, WriteFailure> {
+        let timeout = self.timeout + options.ack.unwrap_or_default();
+        let (reply, receiver) = oneshot::channel();
+        let command = Command::WriteSet {
+            configs,
+            options,
+            reply,
+        };
+        self.request(command, receiver, timeout).await
+    }
+
+    pub(crate) async fn dispose(&self, sensor_type: String) -> Result<DesiredConfig, WriteFailure> {
+        let (reply, receiver) = oneshot::channel();
+        let command = Command::Dispose { sensor_type, reply };
+        self.request(command, receiver, self.timeout).await
+    }
+
+    /// Requests waiting in the channel for the writer task.
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.commands.max_capacity() - self.commands.capacity()
+    }
+
+    async fn request<T>(
+        &self,
+        command: Command,
+        receiver: oneshot::Receiver<Result<T, WriteFailure>>,
+        timeout: Duration,
+    ) -> Result<T, WriteFailure> {
+        // waiting for a free slot counts towards the timeout, so a full queue pushes back
+        let request = async {
+            self.commands
+                .send(command)
+                .await
+                .map_err(|_| WriteFailure::Stopped)?;
+            receiver.await.map_err(|_| WriteFailure::Stopped)?
+        };
+        let result = tokio::time::timeout(timeout, request)
+            .await
+            .unwrap_or(Err(WriteFailure::Timeout));
+        if let Err(WriteFailure::Timeout) = result {
+            self.metrics.record_writer_timeout();
+        }
+        result
+    }
+}
+
+type AckTask<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Executes the requests in order. Acknowledgments are awaited alongside later requests, so
+/// a slow reader only delays the request that asked for them.
+async fn run_writer(writer: impl ConfigSink, mut commands: mpsc::Receiver<Command>, metrics: Metrics) {
+    let writer = &writer;
+    let mut acks = FuturesUnordered::<AckTask<'_>>::new();
+    loop {
+        tokio::select! {
+            Some(()) = acks.next(), if !acks.is_empty() => {}
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    break;
+                };
+                let mut batch = vec![command];
+                while batch.len() < MAX_BATCH {
+                    match commands.try_recv() {
+                        Ok(command) => batch.push(command),
+                        Err(_) => break,
+                    }
+                }
+                acks.extend(execute(writer, batch, &metrics).await);
+            }
+        }
+    }
+    println!("writer task end");
+}
+
+/// Index of the later write each write is coalesced into. A write allowing it is coalesced into
+/// the next write of the same key with the same options, unless the key is disposed or written
+/// as part of a set in between.
+fn coalesce(batch: &[Command]) -> Vec<Option<usize>> {
+    let mut superseded_by = vec![None; batch.len()];
+    let mut latest = HashMap::<&str, usize>::new();
+    for (i, command) in batch.iter().enumerate().rev() {
+        match command {
+            Command::Write { config, options, .. } => {
+                let key = config.sensor_type.as_str();
+                let target = latest.get(key).copied().filter(|&j| {
+                    matches!(&batch[j], Command::Write { options: later, .. }
+                        if options.coalesce
+                            && later.require_matched == options.require_matched
+                            && later.record_desired == options.record_desired)
+                });
+                superseded_by[i] = target;
+                latest.insert(key, target.unwrap_or(i));
+            }
+            Command::WriteSet { configs, .. } => {
+                for config in configs {
+                    latest.remove(config.sensor_type.as_str());
+                }
+            }
+            Command::Dispose { sensor_type, .. } => {
+                latest.remove(sensor_type.as_str());
+            }
+        }
+    }
+    superseded_by
+}
+
+async fn execute<'a, W: ConfigSink>(writer: &'a W, batch: Vec<Command>, metrics: &Metrics) -> Vec<AckTask<'a>> {
+    let superseded_by = coalesce(&batch);
+    let mut outcomes = vec![None; batch.len()];
+    let mut coalesced = vec![];
+    let mut acks = vec![];
+    for (i, command) in batch.into_iter().enumerate() {
+        match command {
+            Command::Write {
+                config,
+                options,
+                reply,
+            } => {
+                if let Some(target) = superseded_by[i] {
+                    metrics.record_writer_coalesced();
+                    coalesced.push((reply, target));
+                    continue;
+                }
+                // kept for the callers of the writes coalesced into this one
+                let replacing = superseded_by.contains(&Some(i)).then(|| config.clone());
+                let written = writer.write(config, &options).await.map(|matched_readers| WriteOutcome {
+                    matched_readers,
+                    acknowledgement: None,
+                    superseded_by: None,
+                });
+                outcomes[i] = Some((written.clone(), replacing));
+                match (written, options.ack) {
+                    (Ok(outcome), Some(timeout)) => acks.push(Box::pin(async move {
+                        let acknowledged = writer.acknowledgement(timeout).await.map(|acknowledgement| WriteOutcome {
+                            acknowledgement: Some(acknowledgement),
+                            ..outcome
+                        });
+                        let _ = reply.send(acknowledged);
+                    }) as AckTask<'_>),
+                    (written, _) => {
+                        let _ = reply.send(written);
+                    }
+                }
+            }
+            Command::WriteSet {
+                configs,
+                options,
+                reply,
+            } => {
+                if options.ack.is_some() && !writer.reliable() {
+                    let _ = reply.send(Err(WriteFailure::NotReliable));
+                    continue;
+                }
+                let mut written = vec![];
+                for config in configs {
+                    let result = writer.write(config, &options).await;
+                    let failed = result.is_err();
+                    written.push(result);
+                    if failed {
+                        break;
+                    }
+                }
+                let outcome = SetOutcome {
+                    written,
+                    acknowledgement: None,
+                };
+                match options.ack {
+                    Some(timeout) if outcome.written.iter().all(Result::is_ok) => acks.push(Box::pin(async move {
+                        let acknowledged = writer.acknowledgement(timeout).await.map(|acknowledgement| SetOutcome {
+                            acknowledgement: Some(acknowledgement),
+                            ..outcome
+                        });
+                        let _ = reply.send(acknowledged);
+                    }) as AckTask<'_>),
+                    _ => {
+                        let _ = reply.send(Ok(outcome));
+                    }
+                }
+            }
+            Command::Dispose { sensor_type, reply } => {
+                let _ = reply.send(writer.dispose(&sensor_type).await);
+            }
+        }
+    }
+    // a coalesced write shares the outcome of the write that replaced it
+    for (reply, target) in coalesced {
+        if let Some((outcome, replacing)) = outcomes[target].clone() {
+            let _ = reply.send(outcome.map(|outcome| WriteOutcome {
+                superseded_by: replacing,
+                ..outcome
+            }));
+        }
+    }
+    acks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tokio::sync::Semaphore;
+
+    /// Records written configs. A write waits for a permit, so the test decides when it ends.
+    #[derive(Clone)]
+    struct FakeSink {
+        attempts: Arc<AtomicUsize>,
+        permits: Arc<Semaphore>,
+        written: Arc<Mutex<Vec<SensorConfig>>>,
+    }
+
+    impl ConfigSink for FakeSink {
+        fn reliable(&self) -> bool {
+            true
+        }
+
+        async fn write(&self, config: SensorConfig, _options: &WriteOptions) -> Result<usize, WriteFailure> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            self.permits.acquire().await.expect("semaphore is open").forget();
+            self.written.lock().unwrap().push(config);
+            Ok(1)
+        }
+
+        async fn dispose(&self, sensor_type: &str) -> Result<DesiredConfig, WriteFailure> {
+            Ok(DesiredConfig {
+                sensor_type: sensor_type.to_string(),
+                config: None,
+                instance_state: InstanceState::Disposed,
+                updated_at: 0,
+            })
+        }
+
+        async fn acknowledgement(&self, _timeout: Duration) -> Result<Acknowledgement, WriteFailure> {
+            Ok(Acknowledgement::default())
+        }
+    }
+
+    impl FakeSink {
+        fn new() -> Self {
+            FakeSink {
+                attempts: Arc::default(),
+                permits: Arc::new(Semaphore::new(0)),
+                written: Arc::default(),
+            }
+        }
+
+        fn frequencies(&self) -> Vec<u32> {
+            self.written.lock().unwrap().iter().map(|config| config.frequency).collect()
+        }
+    }
+
+    fn config(sensor_type: &str, frequency: u32) -> SensorConfig {
+        SensorConfig {
+            sensor_type: sensor_type.to_string(),
+            frequency,
+            ..SensorConfig::default()
+        }
+    }
+
+    fn coalescing() -> WriteOptions {
+        WriteOptions {
+            coalesce: true,
+            ..WriteOptions::default()
+        }
+    }
+
+    fn write(sensor_type: &str, frequency: u32, options: WriteOptions) -> Command {
+        let (reply, _) = oneshot::channel();
+        Command::Write {
+            config: config(sensor_type, frequency),
+            options,
+            reply,
+        }
+    }
+
+    fn spawn(sink: &FakeSink, queue_capacity: usize, timeout_ms: u64) -> WriterHandle {
+        let config = WriterConfig {
+            queue_capacity,
+            timeout_ms,
+        };
+        WriterHandle::spawn(sink.clone(), &config, Metrics::new().unwrap())
+    }
+
+    async fn until(condition: impl Fn() -> bool) {
+        let wait = async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("condition not met within 5 s");
+    }
+
+    #[test]
+    fn coalesce_replaces_earlier_writes_with_the_latest() {
+        let batch = vec![
+            write("a", 1, coalescing()),
+            write("b", 1, coalescing()),
+            write("a", 2, coalescing()),
+            write("a", 3, WriteOptions::default()),
+        ];
+        assert_eq!(coalesce(&batch), vec![Some(3), None, Some(3), None]);
+    }
+
+    #[test]
+    fn coalesce_keeps_writes_that_do_not_allow_it() {
+        let batch = vec![write("a", 1, WriteOptions::default()), write("a", 2, coalescing())];
+        assert_eq!(coalesce(&batch), vec![None, None]);
+    }
+
+    #[test]
+    fn coalesce_needs_the_same_options() {
+        let required = WriteOptions {
+            require_matched: true,
+            ..coalescing()
+        };
+        let batch = vec![write("a", 1, coalescing()), write("a", 2, required)];
+        assert_eq!(coalesce(&batch), vec![None, None]);
+    }
+
+    #[test]
+    fn coalesce_stops_at_dispose_and_sets() {
+        let (reply, _) = oneshot::channel();
+        let dispose = Command::Dispose {
+            sensor_type: "a".to_string(),
+            reply,
+        };
+        let (reply, _) = oneshot::channel();
+        let set = Command::WriteSet {
+            configs: vec![config("b", 2)],
+            options: WriteOptions::default(),
+            reply,
+        };
+        let batch = vec![
+            write("a", 1, coalescing()),
+            dispose,
+            write("a", 2, coalescing()),
+            write("b", 1, coalescing()),
+            set,
+            write("b", 3, coalescing()),
+        ];
+        assert_eq!(coalesce(&batch), vec![None, None, None, None, None, None]);
+    }
+
+    #[tokio::test]
+    async fn queued_writes_of_a_key_are_coalesced() {
+        let sink = FakeSink::new();
+        let writer = spawn(&sink, 8, 5000);
+        let first = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write(config("a", 1), coalescing()).await }
+        });
+        until(|| sink.attempts.load(Ordering::SeqCst) == 1).await;
+        let second = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write(config("a", 2), coalescing()).await }
+        });
+        until(|| writer.queue_depth() == 1).await;
+        let third = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write(config("a", 3), coalescing()).await }
+        });
+        until(|| writer.queue_depth() == 2).await;
+        sink.permits.add_permits(8);
+
+        assert!(first.await.unwrap().unwrap().superseded_by.is_none());
+        let second = second.await.unwrap().unwrap();
+        assert_eq!(second.superseded_by.map(|config| config.frequency), Some(3));
+        assert!(third.await.unwrap().unwrap().superseded_by.is_none());
+        assert_eq!(sink.frequencies(), vec![1, 3]);
+        assert_eq!(writer.queue_depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn writes_that_do_not_allow_it_are_all_written() {
+        let sink = FakeSink::new();
+        let writer = spawn(&sink, 8, 5000);
+        let mut requests = vec![];
+        for frequency in 1..=3 {
+            requests.push(tokio::spawn({
+                let writer = writer.clone();
+                async move { writer.write(config("a", frequency), WriteOptions::default()).await }
+            }));
+            until(|| sink.attempts.load(Ordering::SeqCst) == 1 && writer.queue_depth() == frequency as usize - 1).await;
+        }
+        sink.permits.add_permits(8);
+        for request in requests {
+            assert!(request.await.unwrap().unwrap().superseded_by.is_none());
+        }
+        assert_eq!(sink.frequencies(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn full_queue_pushes_back_until_the_timeout() {
+        let sink = FakeSink::new();
+        let writer = spawn(&sink, 1, 50);
+        let first = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write(config("a", 1), WriteOptions::default()).await }
+        });
+        until(|| sink.attempts.load(Ordering::SeqCst) == 1).await;
+        let second = tokio::spawn({
+            let writer = writer.clone();
+            async move { writer.write(config("b", 1), WriteOptions::default()).await }
+        });
+        until(|| writer.queue_depth() == 1).await;
+
+        let third = writer.write(config("c", 1), WriteOptions::default()).await;
+        assert!(matches!(third, Err(WriteFailure::Timeout)));
+        assert!(matches!(first.await.unwrap(), Err(WriteFailure::Timeout)));
+        assert!(matches!(second.await.unwrap(), Err(WriteFailure::Timeout)));
+
+        // requests that timed out may still be written
+        sink.permits.add_permits(8);
+        until(|| sink.frequencies().len() == 2).await;
+        assert_eq!(writer.queue_depth(), 0);
+    }
+}
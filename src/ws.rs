@@ -0,0 +1,171 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::error::DDS_UNAVAILABLE;
+use crate::writer::WriteOptions;
+use crate::validation;
+use crate::{AppState, SensorConfig, SensorStatus};
+
+/// Message sent by a console over the control channel.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Write a SensorConfig through the gateway's DataWriter. `id` is echoed in the ack.
+    Config {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        config: SensorConfig,
+    },
+    /// Limit status updates to the given sensor types. `null` receives every sensor.
+    Subscribe { sensor_type: Option<Vec<String>> },
+}
+
+/// Message sent by the gateway over the control channel.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Status {
+        seq: u64,
+        disposed: bool,
+        status: SensorStatus,
+    },
+    Ack {
+        id: Option<serde_json::Value>,
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        /// A later config for the same sensor was written instead, `config` holds it
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        coalesced: bool,
+        config: SensorConfig,
+    },
+    Subscribed {
+        sensor_type: Option<Vec<String>>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[utoipa::path(
+    get,
+    path = "/sensor/ws",
+    responses(
+        (status = 101, description = "Switch to the WebSocket control channel. \
+            Send `{\"type\":\"config\",\"id\":1,\"config\":{...}}` to write a SensorConfig \
+            or `{\"type\":\"subscribe\",\"sensor_type\":[...]}` to filter status updates; \
+            receive `status`, `ack`, `subscribed` and `error` messages."),
+    ),
+    tag = "get_handler_sensor_ws"
+)]
+pub(crate) async fn get_handler_sensor_ws(
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| control_channel(socket, state))
+}
+
+async fn control_channel(mut socket: WebSocket, state: AppState) {
+    let mut status_events = state.status_events.subscribe();
+    let mut filter: Option<Vec<String>> = None;
+
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => Some(handle_client_message(&state, &mut filter, &text).await),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // ping/pong is answered by axum, binary frames are not part of the protocol
+                Some(Ok(_)) => None,
+            },
+            event = status_events.recv() => match event {
+                Ok(event) => filter
+                    .as_ref()
+                    .is_none_or(|sensor_types| sensor_types.contains(&event.status.sensor_type))
+                    .then_some(ServerMessage::Status {
+                        seq: event.seq,
+                        disposed: event.disposed,
+                        status: event.status,
+                    }),
+                Err(RecvError::Lagged(skipped)) => Some(ServerMessage::Error {
+                    error: format!("{skipped} status updates were dropped, client is too slow"),
+                }),
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        if let Some(reply) = reply {
+            let Ok(text) = serde_json::to_string(&reply) else {
+                continue;
+            };
+            if socket.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+async fn handle_client_message(
+    state: &AppState,
+    filter: &mut Option<Vec<String>>,
+    text: &str,
+) -> ServerMessage {
+    match serde_json::from_str::<ClientMessage>(text) {
+        Ok(ClientMessage::Config { id, config }) => {
+            let report = validation::validate_config(&state.validation, &config);
+            if !report.valid {
+                return ServerMessage::Ack {
+                    id,
+                    ok: false,
+                    error: Some(report.describe()),
+                    coalesced: false,
+                    config,
+                };
+            }
+            let Some(writer) = state.writer.get() else {
+                return ServerMessage::Ack {
+                    id,
+                    ok: false,
+                    error: Some(DDS_UNAVAILABLE.to_string()),
+                    coalesced: false,
+                    config,
+                };
+            };
+            let options = WriteOptions {
+                record_desired: true,
+                coalesce: true,
+                ..WriteOptions::default()
+            };
+            let written = writer.write(config.clone(), options).await;
+            match written {
+                Ok(outcome) => ServerMessage::Ack {
+                    id,
+                    ok: true,
+                    error: None,
+                    coalesced: outcome.superseded_by.is_some(),
+                    config: outcome.superseded_by.unwrap_or(config),
+                },
+                Err(e) => ServerMessage::Ack {
+                    id,
+                    ok: false,
+                    error: Some(e.to_string()),
+                    coalesced: false,
+                    config,
+                },
+            }
+        }
+        Ok(ClientMessage::Subscribe { sensor_type }) => {
+            filter.clone_from(&sensor_type);
+            ServerMessage::Subscribed { sensor_type }
+        }
+        Err(e) => ServerMessage::Error {
+            error: format!("invalid message: {e}"),
+        },
+    }
+}